@@ -0,0 +1,353 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module holds animations, such as animated GIFs and APNGs, and the player
+//! used to show them on a layer.
+
+use std::{io::Read, time::{Duration, Instant}};
+use crate::{bitmap::{ImageFormat, RgbaImage}, error::ErrorKind, Layer, Result};
+#[cfg(any(feature = "png", feature = "gif"))]
+use crate::formats;
+
+/// One frame of an animation
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Frame {
+    /// The whole image shown during this frame. Frames that only change part of
+    /// the image have already been drawn over the frames before them.
+    pub image: RgbaImage,
+
+    /// How long the frame is shown for
+    pub delay: Duration,
+}
+
+impl Frame {
+    /// Creates a frame
+    pub fn new(image: RgbaImage, delay: Duration) -> Self {
+        Self { image, delay }
+    }
+}
+
+/// An animation, and how far through it is. It is moved forward by `tick`, and
+/// drawn with `Layer::draw_animation`.
+#[derive(Clone, Debug)]
+pub struct Animation {
+    frames: Vec<Frame>,
+    plays: Option<u32>,
+    /// How long the animation has been playing for, including every time it looped
+    elapsed: Duration,
+    last_tick: Option<Instant>,
+    paused: bool,
+}
+
+impl Animation {
+    /// Creates an animation from its frames, which plays `plays` times, or forever
+    /// if it is `None`
+    ///
+    /// # Panics
+    ///
+    /// Panics if there are no frames
+    pub fn new(frames: Vec<Frame>, plays: Option<u32>) -> Self {
+        assert!(!frames.is_empty(), "An animation needs at least one frame");
+        Self { frames, plays, elapsed: Duration::from_secs(0), last_tick: None, paused: false }
+    }
+
+    /// Decodes an animated GIF (with the `gif` feature) or APNG (with the `png`
+    /// feature). Images that aren't animated, in any format that `draw_img` can
+    /// draw, become an animation with one frame.
+    pub fn from_reader(src: &mut impl Read) -> Result<Self> {
+        let mut data = Vec::new();
+        src.read_to_end(&mut data)?;
+        Self::from_bytes(&data)
+    }
+
+    /// Decodes an animation from its bytes, the same way as `from_reader`
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let (frames, plays) = match ImageFormat::detect(data) {
+            #[cfg(feature = "gif")]
+            Some(ImageFormat::Gif) => formats::gif_animation(data)?,
+            #[cfg(not(feature = "gif"))]
+            Some(ImageFormat::Gif) => return Err(ErrorKind::ImageError("GIF animations need the \"gif\" feature".to_string())),
+            #[cfg(feature = "png")]
+            Some(ImageFormat::Png) => formats::apng(data)?,
+            #[cfg(not(feature = "png"))]
+            Some(ImageFormat::Png) => return Err(ErrorKind::ImageError("PNG animations need the \"png\" feature".to_string())),
+            _ => (vec![Frame::new(RgbaImage::from_bytes(data)?, Duration::from_secs(0))], Some(1)),
+        };
+        if frames.is_empty() {
+            return Err(ErrorKind::ImageError("The animation has no frames".to_string()));
+        }
+        Ok(Self::new(frames, plays))
+    }
+
+    /// The frames of the animation
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// How many times the animation plays, or `None` if it plays forever
+    pub fn plays(&self) -> Option<u32> {
+        self.plays
+    }
+
+    /// Sets how many times the animation plays, or `None` to play it forever
+    pub fn set_plays(&mut self, plays: Option<u32>) {
+        self.plays = plays;
+    }
+
+    /// How long it takes to play the animation once
+    pub fn duration(&self) -> Duration {
+        self.frames.iter().map(|frame| frame.delay).sum()
+    }
+
+    /// Moves the animation forward by however long it has been since the last time
+    /// this was called, and returns whether the frame that should be shown changed.
+    /// `now` is meant to be the time returned by `Canvas::wait_for_frame` (or given
+    /// to a `Canvas::request_animation_frame` callback), so that the animation keeps
+    /// time with the frames that are drawn.
+    ///
+    /// The first call only starts the clock, so the first frame is always shown.
+    pub fn tick(&mut self, now: Instant) -> bool {
+        let frame = self.current_frame();
+        if let Some(last_tick) = self.last_tick {
+            if !self.paused {
+                self.elapsed += now.saturating_duration_since(last_tick);
+            }
+        }
+        self.last_tick = Some(now);
+        self.current_frame() != frame
+    }
+
+    /// Stops the animation at the frame it's on
+    pub fn pause(&mut self) {
+        self.paused = true;
+        self.last_tick = None;
+    }
+
+    /// Starts the animation again after it was paused. The time it was paused for
+    /// isn't counted.
+    pub fn play(&mut self) {
+        self.paused = false;
+        self.last_tick = None;
+    }
+
+    /// Whether the animation is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Whether the animation has played as many times as it should, so that it
+    /// stays on its last frame
+    pub fn is_finished(&self) -> bool {
+        match self.plays {
+            // A very long animation could play so many times that it can't end
+            Some(plays) => match self.duration().checked_mul(plays) {
+                Some(end) => self.elapsed >= end,
+                None => false,
+            },
+            None => false,
+        }
+    }
+
+    /// Goes to a time in the animation, counted from the start of the first time
+    /// it plays. Times past the end of one play go into the next one.
+    pub fn seek(&mut self, time: Duration) {
+        self.elapsed = time;
+    }
+
+    /// Goes to the start of a frame, in the play the animation is on now
+    ///
+    /// # Panics
+    ///
+    /// Panics if there is no frame at `index`
+    pub fn seek_frame(&mut self, index: usize) {
+        assert!(index < self.frames.len(), "Frame {} is out of bounds for an animation with {} frames", index, self.frames.len());
+        let start: Duration = self.frames[..index].iter().map(|frame| frame.delay).sum();
+        self.elapsed = self.play_start() + start;
+    }
+
+    /// The time in the animation, counted from the start of the first time it plays
+    pub fn position(&self) -> Duration {
+        self.elapsed
+    }
+
+    /// The index of the frame that should be shown
+    pub fn current_frame(&self) -> usize {
+        if self.is_finished() {
+            return self.frames.len() - 1;
+        }
+        let mut time = self.elapsed - self.play_start();
+        for (i, frame) in self.frames.iter().enumerate() {
+            if time < frame.delay {
+                return i;
+            }
+            time -= frame.delay;
+        }
+        // This only happens if every frame has a delay of 0
+        self.frames.len() - 1
+    }
+
+    /// How long until the frame that should be shown changes, or `None` if it won't
+    /// because the animation is paused or finished. This can be used to wait for
+    /// the next frame, instead of drawing the same one over and over.
+    pub fn time_to_next_frame(&self) -> Option<Duration> {
+        if self.paused || self.is_finished() || self.duration() == Duration::from_secs(0) {
+            return None;
+        }
+        let frame = self.current_frame();
+        let end: Duration = self.frames[..=frame].iter().map(|frame| frame.delay).sum();
+        Some(self.play_start() + end - self.elapsed)
+    }
+
+    /// The time at which the play the animation is on started
+    fn play_start(&self) -> Duration {
+        let duration = self.duration().as_nanos();
+        if duration == 0 {
+            return Duration::from_secs(0);
+        }
+        let mut plays = self.elapsed.as_nanos() / duration;
+        if let Some(max) = self.plays {
+            plays = plays.min(max.saturating_sub(1) as u128);
+        }
+        // This is never more than `elapsed`, so it fits in a `Duration`
+        let start = plays * duration;
+        Duration::new((start / 1_000_000_000) as u64, (start % 1_000_000_000) as u32)
+    }
+}
+
+impl<'a> Layer<'a> {
+    /// Draws the frame of an animation that should be shown now, with its top left
+    /// corner at `(x, y)`. Each frame replaces the one before it, so the pixels
+    /// that are transparent in the frame are made transparent on the layer, which
+    /// means the animation should usually have a layer to itself.
+    pub fn draw_animation(&mut self, x: u16, y: u16, animation: &Animation) -> Result<()> {
+        let image = &animation.frames[animation.current_frame()].image;
+        self.validate_image(x, y, image.width(), image.height())?;
+        for py in 0..image.height() {
+            for px in 0..image.width() {
+                // This fits, since the whole image is in bounds
+                let p = (x + px as u16, y + py as u16);
+                self.clear_px(p)?;
+                self.draw_img_px((p.0 as i32, p.1 as i32), image.get_pixel(px, py))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    /// An animation with blank frames that are shown for these many milliseconds
+    fn animation(delays: &[u64], plays: Option<u32>) -> Animation {
+        Animation::new(delays.iter().map(|&delay| Frame::new(RgbaImage::new(1, 1), ms(delay))).collect(), plays)
+    }
+
+    #[test]
+    #[should_panic]
+    fn animation_needs_frames() {
+        Animation::new(Vec::new(), None);
+    }
+
+    #[test]
+    fn frames_follow_time() {
+        let mut animation = animation(&[100, 50, 200], None);
+        assert_eq!(animation.duration(), ms(350));
+        let frames: Vec<usize> = [0, 99, 100, 149, 150, 349, 350, 460].iter().map(|&time| {
+            animation.seek(ms(time));
+            animation.current_frame()
+        }).collect();
+        assert_eq!(frames, [0, 0, 1, 1, 2, 2, 0, 1]);
+    }
+
+    #[test]
+    fn finished_animation_stays_on_last_frame() {
+        let mut animation = animation(&[100, 100], Some(2));
+        animation.seek(ms(350));
+        assert_eq!((animation.current_frame(), animation.is_finished()), (1, false));
+        assert_eq!(animation.play_start(), ms(200));
+        animation.seek(ms(1000));
+        assert_eq!((animation.current_frame(), animation.is_finished()), (1, true));
+        assert_eq!(animation.play_start(), ms(200));
+        assert_eq!(animation.time_to_next_frame(), None);
+    }
+
+    #[test]
+    fn seek_frame_stays_in_play() {
+        let mut animation = animation(&[100, 50, 200], None);
+        animation.seek(ms(800));
+        animation.seek_frame(2);
+        assert_eq!(animation.position(), ms(850));
+        assert_eq!(animation.time_to_next_frame(), Some(ms(200)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn seek_frame_out_of_bounds() {
+        animation(&[100], None).seek_frame(1);
+    }
+
+    #[test]
+    fn tick_moves_forward_unless_paused() {
+        let mut animation = animation(&[100, 100], None);
+        let start = Instant::now();
+        assert!(!animation.tick(start));
+        assert!(animation.tick(start + ms(150)));
+        animation.pause();
+        assert!(!animation.tick(start + ms(300)));
+        animation.play();
+        assert!(!animation.tick(start + ms(400)));
+        assert_eq!(animation.position(), ms(150));
+        assert!(animation.tick(start + ms(460)));
+    }
+
+    #[test]
+    fn long_animations_dont_overflow() {
+        let long = Duration::from_secs(u64::MAX / 4);
+        let mut animation = Animation::new(vec![Frame::new(RgbaImage::new(1, 1), long); 2], Some(u32::MAX));
+        animation.seek(long + ms(1));
+        assert_eq!((animation.current_frame(), animation.is_finished()), (1, false));
+        animation.seek(Duration::new(u64::MAX, 0));
+        assert_eq!(animation.play_start(), long * 4);
+        assert_eq!(animation.current_frame(), 0);
+    }
+
+    /// A 1x1 PNG with an animation control chunk that says it has `frames` frames,
+    /// but no frame control chunks
+    #[cfg(feature = "png")]
+    fn apng(frames: u32) -> Vec<u8> {
+        let crc = |bytes: &[u8]| !bytes.iter().fold(!0u32, |crc, &byte| {
+            (0..8).fold(crc ^ byte as u32, |crc, _| if crc & 1 == 1 { (crc >> 1) ^ 0xEDB8_8320 } else { crc >> 1 })
+        });
+        let mut png = Vec::new();
+        RgbaImage::new(1, 1).write_png(&mut png).unwrap();
+        let mut chunk = b"acTL".to_vec();
+        chunk.extend_from_slice(&frames.to_be_bytes());
+        chunk.extend_from_slice(&0u32.to_be_bytes());
+        let crc = crc(&chunk);
+        let mut actl = 8u32.to_be_bytes().to_vec();
+        actl.extend_from_slice(&chunk);
+        actl.extend_from_slice(&crc.to_be_bytes());
+        // The signature and the header chunk take up the first 33 bytes
+        png.splice(33..33, actl);
+        png
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn forged_frame_counts_are_errors() {
+        assert!(Animation::from_bytes(&apng(u32::MAX)).is_err());
+        assert!(Animation::from_bytes(&apng(0)).is_err());
+    }
+
+    #[test]
+    fn frames_without_delay() {
+        let mut animation = animation(&[0, 0], None);
+        animation.seek(ms(10));
+        assert_eq!((animation.current_frame(), animation.time_to_next_frame()), (1, None));
+    }
+}
@@ -0,0 +1,136 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! Anti-aliased versions of the drawing functions. Pixels that are only partly
+//! covered by a shape are mixed with the color that is already in the layer,
+//! which makes edges look much smoother in half block mode.
+
+use crate::{paint::{IntoPaint, Paint, Shader}, raster, color, Color, FillRule, FPoint, Layer, Result};
+
+impl<'a> Layer<'a> {
+    /// Draws an anti-aliased line connecting points `p0` and `p1`. If `color` is
+    /// `None`, the current stroke color is used.
+    pub fn line_aa(&mut self, p0: FPoint, p1: FPoint, color: impl Into<Option<Color>>) -> Result<()> {
+        let color = color.into().unwrap_or(self.state.stroke_color);
+        let p0 = self.state.transform.apply(p0);
+        let p1 = self.state.transform.apply(p1);
+        self.validate_f_points(&[p0, p1])?;
+        if self.state.line_width > 1.0 {
+            let quad = raster::thick_line_quad(p0, p1, self.state.line_width);
+            return self.fill_polygon_aa_px(&quad, FillRule::NonZero, &Paint::Solid(color));
+        }
+        for (p, coverage) in raster::wu_line(p0, p1) {
+            self.blend(p, color, coverage)?;
+        }
+        Ok(())
+    }
+
+    /// Draws the outline of an anti-aliased circle, using the current line width.
+    /// If `color` is `None`, the current stroke color is used.
+    pub fn stroke_circle_aa(&mut self, center: FPoint, radius: f32, color: impl Into<Option<Color>>) -> Result<()> {
+        let color = color.into().unwrap_or(self.state.stroke_color);
+        let half_width = self.state.line_width / 2.0;
+        self.circle_aa(center, radius, &Paint::Solid(color), |distance, radius| (distance - radius).abs() <= half_width)
+    }
+
+    /// Draws and fills an anti-aliased circle. If `paint` is `None`, the current
+    /// fill paint is used.
+    pub fn fill_circle_aa(&mut self, center: FPoint, radius: f32, paint: impl IntoPaint) -> Result<()> {
+        let paint = self.fill_paint(paint);
+        self.circle_aa(center, radius, &paint, |distance, radius| distance <= radius)
+    }
+
+    /// Draws the outline of an anti-aliased polygon, connecting the last point
+    /// back to the first. If `color` is `None`, the current stroke color is used.
+    pub fn stroke_polygon_aa(&mut self, points: &[FPoint], color: impl Into<Option<Color>>) -> Result<()> {
+        let color = color.into().unwrap_or(self.state.stroke_color);
+        for (i, &p) in points.iter().enumerate() {
+            self.line_aa(p, points[(i + 1) % points.len()], color)?;
+        }
+        Ok(())
+    }
+
+    /// Draws and fills an anti-aliased polygon. If `paint` is `None`, the current
+    /// fill paint is used.
+    pub fn fill_polygon_aa(&mut self, points: &[FPoint], rule: FillRule, paint: impl IntoPaint) -> Result<()> {
+        let paint = self.fill_paint(paint);
+        let points: Vec<FPoint> = points.iter().map(|&p| self.state.transform.apply(p)).collect();
+        self.validate_f_points(&points)?;
+        self.fill_polygon_aa_px(&points, rule, &paint)
+    }
+
+    /// Fills a polygon whose points have already been transformed
+    fn fill_polygon_aa_px(&mut self, polygon: &[FPoint], rule: FillRule, paint: &Paint) -> Result<()> {
+        self.fill_contours_aa_px(&[polygon], rule, paint)
+    }
+
+    /// Fills a shape made of several polygons, such as a letter with holes in it,
+    /// whose points have already been transformed
+    pub(crate) fn fill_contours_aa_px<P: AsRef<[FPoint]>>(&mut self, contours: &[P], rule: FillRule, paint: &Paint) -> Result<()> {
+        let shader = Shader::new(paint, &self.state.transform);
+        let (width, height) = self.resolution();
+        let points: Vec<FPoint> = contours.iter().flat_map(|contour| contour.as_ref().iter().copied()).collect();
+        let (left, top, right, bottom) = raster::bounds(&points, 0.0, width as u16, height as u16);
+        for y in top..bottom {
+            for x in left..right {
+                let coverage = raster::coverage((x, y), |p| {
+                    rule.fills(contours.iter().map(|contour| raster::winding(contour.as_ref(), p)).sum())
+                });
+                self.blend_shaded((x, y), &shader, coverage)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws a circle, where `inside` says whether a point at some distance from the
+    /// center is part of it, given the radius
+    fn circle_aa(&mut self, center: FPoint, radius: f32, paint: &Paint, inside: impl Fn(f32, f32) -> bool) -> Result<()> {
+        let shader = Shader::new(paint, &self.state.transform);
+        let center = self.state.transform.apply(center);
+        let radius = radius * self.state.transform.scale_factor();
+        self.validate_f_points(&[center])?;
+        let (width, height) = self.resolution();
+        let margin = radius + self.state.line_width;
+        let (left, top, right, bottom) = raster::bounds(&[center], margin, width as u16, height as u16);
+        for y in top..bottom {
+            for x in left..right {
+                let coverage = raster::coverage((x, y), |p| {
+                    inside((p.0 - center.0).hypot(p.1 - center.1), radius)
+                });
+                self.blend_shaded((x, y), &shader, coverage)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Draws `color` over part of a pixel, where `coverage` is how much of it, from
+    /// 0 to 1. It is mixed with what's under it, which is either this layer's pixel
+    /// or, if that is transparent, the layers under this one. If every layer is
+    /// transparent there, the terminal's background shows through, and its color
+    /// isn't known, so the pixel is only drawn if at least half of it is covered.
+    /// Pixels outside of the layer are skipped.
+    pub(crate) fn blend(&mut self, p: (i32, i32), color: Color, coverage: f32) -> Result<()> {
+        let (width, height) = self.resolution();
+        if coverage <= 0.0 || p.0 < 0 || p.1 < 0 || p.0 as usize >= width || p.1 as usize >= height {
+            return Ok(());
+        }
+        let p = (p.0 as u16, p.1 as u16);
+        let color = match self.get_pixel(p).or_else(|| self.color_below(p)) {
+            Some(under) => color::mix(under, color, coverage.min(1.0)),
+            None if coverage >= 0.5 => color,
+            None => return Ok(()),
+        };
+        self.plot(p, color)
+    }
+
+    /// Blends the color a shader gives a pixel into it
+    fn blend_shaded(&mut self, p: (i32, i32), shader: &Shader<'_>, coverage: f32) -> Result<()> {
+        if p.0 < 0 || p.1 < 0 {
+            return Ok(());
+        }
+        match shader.color_at((p.0 as u16, p.1 as u16)) {
+            Some(color) => self.blend(p, color, coverage),
+            None => Ok(()),
+        }
+    }
+}
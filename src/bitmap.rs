@@ -0,0 +1,440 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module holds decoded images, so they can be drawn without decoding them
+//! again each time.
+
+use std::{fs::File, io::{BufWriter, Read, Write}, ops::Range, path::Path};
+use bmp;
+use crate::{color, error::ErrorKind, formats, Color, Result};
+
+/// The formats that images can be decoded from. BMP can always be decoded, and the
+/// others need the cargo feature of the same name (in lowercase).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ImageFormat {
+    /// Windows bitmap
+    Bmp,
+
+    /// Portable Network Graphics
+    Png,
+
+    /// JPEG
+    Jpeg,
+
+    /// Graphics Interchange Format. Only the first frame is decoded
+    Gif,
+
+    /// WebP. Only the first frame is decoded
+    WebP,
+}
+
+impl ImageFormat {
+    /// Finds the format of an image from its first few bytes (its "magic number")
+    pub fn detect(data: &[u8]) -> Option<Self> {
+        if data.starts_with(b"BM") {
+            Some(ImageFormat::Bmp)
+        }
+        else if data.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+            Some(ImageFormat::Png)
+        }
+        else if data.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(ImageFormat::Jpeg)
+        }
+        else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+            Some(ImageFormat::Gif)
+        }
+        else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+            Some(ImageFormat::WebP)
+        }
+        else {
+            None
+        }
+    }
+
+    /// The cargo feature needed to decode this format
+    fn feature(&self) -> &'static str {
+        match self {
+            ImageFormat::Bmp => "",
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpeg",
+            ImageFormat::Gif => "gif",
+            ImageFormat::WebP => "webp",
+        }
+    }
+}
+
+/// How an image is fit into a rectangle by `Layer::draw_img_scaled`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FitMode {
+    /// Scaled to be as big as it can be while still fitting inside the rectangle,
+    /// keeping its aspect ratio. There can be empty space on two sides.
+    Contain,
+
+    /// Scaled to be as small as it can be while still covering the whole rectangle,
+    /// keeping its aspect ratio. The parts outside the rectangle are cut off.
+    Cover,
+
+    /// Stretched to the size of the rectangle, even if that changes its aspect ratio
+    Stretch,
+
+    /// Not scaled. It is centered in the rectangle and cut off if it is too big.
+    None,
+}
+
+impl FitMode {
+    /// Gets the size an image of size `img` should be scaled to, to fit it into a
+    /// rectangle of size `dst`
+    pub fn size(&self, img: (u32, u32), dst: (u32, u32)) -> (u32, u32) {
+        if img.0 == 0 || img.1 == 0 {
+            return (0, 0);
+        }
+        let scale_x = dst.0 as f64 / img.0 as f64;
+        let scale_y = dst.1 as f64 / img.1 as f64;
+        let scale = match self {
+            FitMode::Contain => scale_x.min(scale_y),
+            FitMode::Cover => scale_x.max(scale_y),
+            FitMode::Stretch => return dst,
+            FitMode::None => return img,
+        };
+        // Rounding could make one side a pixel smaller than the rectangle, which
+        // would leave a gap when covering it
+        let fit = |len: u32, dst: u32| match self {
+            FitMode::Cover => ((len as f64 * scale).round() as u32).max(dst),
+            _ => ((len as f64 * scale).round() as u32).min(dst),
+        };
+        (fit(img.0, dst.0), fit(img.1, dst.1))
+    }
+}
+
+/// How the colors of a scaled image are picked from the pixels of the original
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Filter {
+    /// Each pixel is the color of the nearest pixel in the original. This is the
+    /// fastest, and keeps pixel art sharp.
+    Nearest,
+
+    /// Each pixel is mixed from the four nearest pixels in the original. This looks
+    /// smooth when making an image bigger, but loses detail when making it much
+    /// smaller.
+    Bilinear,
+
+    /// Each pixel is the average of all of the pixels of the original that it
+    /// covers. This is the best one for making photos smaller.
+    Area,
+}
+
+/// An image stored as red, green, blue and alpha values for each pixel
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RgbaImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<[u8; 4]>,
+}
+
+impl RgbaImage {
+    /// Creates a new image where every pixel is transparent
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { width, height, pixels: vec![[0; 4]; width as usize * height as usize] }
+    }
+
+    /// Decodes an image, finding its format from its first few bytes, so that it can
+    /// be read from something that can't seek, like stdin
+    pub fn from_reader(src: &mut impl Read) -> Result<Self> {
+        let mut data = Vec::new();
+        src.read_to_end(&mut data)?;
+        Self::from_bytes(&data)
+    }
+
+    /// Decodes an image from its bytes, finding its format from the first few of them
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        match ImageFormat::detect(data) {
+            Some(ImageFormat::Bmp) => formats::bmp(data),
+            #[cfg(feature = "png")]
+            Some(ImageFormat::Png) => formats::png(data),
+            #[cfg(feature = "jpeg")]
+            Some(ImageFormat::Jpeg) => formats::jpeg(data),
+            #[cfg(feature = "gif")]
+            Some(ImageFormat::Gif) => formats::gif(data),
+            #[cfg(feature = "webp")]
+            Some(ImageFormat::WebP) => formats::webp(data),
+            #[allow(unreachable_patterns)]
+            Some(format) => Err(ErrorKind::ImageError(format!("{:?} images need the \"{}\" feature", format, format.feature()))),
+            None => Err(ErrorKind::ImageError("unknown image format".to_string())),
+        }
+    }
+
+    /// The width of the image
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The height of the image
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Gets the red, green, blue and alpha values of a pixel
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pixel is out of bounds
+    pub fn get_pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        self.pixels[self.index(x, y)]
+    }
+
+    /// Sets the red, green, blue and alpha values of a pixel
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pixel is out of bounds
+    pub fn set_pixel(&mut self, x: u32, y: u32, rgba: [u8; 4]) {
+        let i = self.index(x, y);
+        self.pixels[i] = rgba;
+    }
+
+    /// Makes every pixel that is `key` transparent. Sprites are often drawn on a
+    /// background of a color that they don't use, like magenta, so that it can be
+    /// taken out this way.
+    pub fn apply_color_key(&mut self, key: Color) {
+        if let Some((r, g, b)) = color::to_rgb(key) {
+            for px in self.pixels.iter_mut() {
+                if px[..3] == [r, g, b] {
+                    px[3] = 0;
+                }
+            }
+        }
+    }
+
+    /// Creates a copy of the image scaled to a new size. Colors are mixed by how
+    /// opaque they are, so transparent pixels don't bleed into the ones around them.
+    pub fn resize(&self, width: u32, height: u32, filter: Filter) -> Self {
+        self.resize_part((width, height), (0, 0, width, height), filter)
+    }
+
+    /// Scales the image to `size` the same way as `resize`, but only makes the part
+    /// of the scaled image at `part`, which is `(x, y, width, height)`. This is the
+    /// same as cutting that part out of the scaled image, without making all of it.
+    pub(crate) fn resize_part(&self, size: (u32, u32), part: (u32, u32, u32, u32), filter: Filter) -> Self {
+        let (x, y, width, height) = part;
+        let width = width.min(size.0.saturating_sub(x));
+        let height = height.min(size.1.saturating_sub(y));
+        let mut out = Self::new(width, height);
+        if self.width == 0 || self.height == 0 {
+            return out;
+        }
+        let weights_x = weights(self.width, size.0, x..x + width, filter);
+        let weights_y = weights(self.height, size.1, y..y + height, filter);
+        for (y, row_weights) in weights_y.iter().enumerate() {
+            for (x, col_weights) in weights_x.iter().enumerate() {
+                let mut sum = [0.0f32; 4];
+                let mut total = 0.0;
+                for &(sy, wy) in row_weights {
+                    for &(sx, wx) in col_weights {
+                        let [r, g, b, a] = self.get_pixel(sx, sy);
+                        let weight = wx * wy;
+                        let alpha = a as f32 * weight;
+                        sum[0] += r as f32 * alpha;
+                        sum[1] += g as f32 * alpha;
+                        sum[2] += b as f32 * alpha;
+                        sum[3] += alpha;
+                        total += weight;
+                    }
+                }
+                if sum[3] > 0.0 {
+                    let channel = |i: usize| (sum[i] / sum[3]).round().min(255.0) as u8;
+                    let alpha = (sum[3] / total).round().min(255.0) as u8;
+                    out.set_pixel(x as u32, y as u32, [channel(0), channel(1), channel(2), alpha]);
+                }
+            }
+        }
+        out
+    }
+
+    /// Encodes the image as a BMP. BMPs don't have alpha, so the image is drawn on
+    /// black.
+    pub fn write_bmp(&self, dst: &mut impl Write) -> Result<()> {
+        let mut img = bmp::Image::new(self.width, self.height);
+        for (x, y) in img.coordinates() {
+            let [r, g, b, a] = self.get_pixel(x, y);
+            let channel = |c: u8| (c as u16 * a as u16 / 255) as u8;
+            img.set_pixel(x, y, bmp::Pixel::new(channel(r), channel(g), channel(b)));
+        }
+        img.to_writer(dst)?;
+        Ok(())
+    }
+
+    /// Saves the image to a BMP file, the same way as `write_bmp`
+    pub fn save_bmp(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.write_bmp(&mut BufWriter::new(File::create(path)?))
+    }
+
+    /// Encodes the image as a PNG. This needs the `png` feature.
+    #[cfg(feature = "png")]
+    pub fn write_png(&self, dst: &mut impl Write) -> Result<()> {
+        formats::write_png(self, dst)
+    }
+
+    /// Saves the image to a PNG file. This needs the `png` feature.
+    #[cfg(feature = "png")]
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<()> {
+        self.write_png(&mut BufWriter::new(File::create(path)?))
+    }
+
+    /// Gets the color of a pixel, or `None` if it is mostly transparent
+    pub(crate) fn color_at(&self, x: u32, y: u32) -> Option<Color> {
+        let [r, g, b, a] = self.get_pixel(x, y);
+        if a < 128 {
+            None
+        }
+        else {
+            Some(Color::Rgb { r, g, b })
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "Pixel ({}, {}) is out of bounds for an image of size {}x{}", x, y, self.width, self.height);
+        y as usize * self.width as usize + x as usize
+    }
+}
+
+/// Gets the pixels of a row or column of `src` pixels that each of `dst` scaled
+/// pixels in `range` is made from, and how much each of them counts. Scaling is
+/// done one direction at a time, so the weight of a pixel is its weight across
+/// times its weight down.
+fn weights(src: u32, dst: u32, range: Range<u32>, filter: Filter) -> Vec<Vec<(u32, f32)>> {
+    let scale = src as f32 / dst as f32;
+    let last = src - 1;
+    range.map(|i| match filter {
+        Filter::Nearest => vec![((((i as f32 + 0.5) * scale) as u32).min(last), 1.0)],
+        Filter::Bilinear => {
+            // The middle of the scaled pixel, measured so that the middle of the first
+            // original pixel is 0
+            let center = ((i as f32 + 0.5) * scale - 0.5).max(0.0).min(last as f32);
+            let left = center.floor();
+            let t = center - left;
+            let left = left as u32;
+            vec![(left, 1.0 - t), ((left + 1).min(last), t)]
+        },
+        Filter::Area => {
+            let start = i as f32 * scale;
+            let end = (i as f32 + 1.0) * scale;
+            (start.floor() as u32..(end.ceil() as u32).min(src))
+                .map(|j| (j, end.min(j as f32 + 1.0) - start.max(j as f32)))
+                .filter(|&(_, weight)| weight > 0.0)
+                .collect()
+        },
+    }).collect()
+}
+
+impl From<&bmp::Image> for RgbaImage {
+    fn from(img: &bmp::Image) -> Self {
+        let mut out = Self::new(img.get_width(), img.get_height());
+        for (x, y) in img.coordinates() {
+            let px = img.get_pixel(x, y);
+            out.set_pixel(x, y, [px.r, px.g, px.b, 255]);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_are_detected() {
+        assert_eq!(ImageFormat::detect(b"BM\0\0"), Some(ImageFormat::Bmp));
+        assert_eq!(ImageFormat::detect(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0]), Some(ImageFormat::Png));
+        assert_eq!(ImageFormat::detect(&[0xFF, 0xD8, 0xFF, 0xE0]), Some(ImageFormat::Jpeg));
+        assert_eq!(ImageFormat::detect(b"GIF87a"), Some(ImageFormat::Gif));
+        assert_eq!(ImageFormat::detect(b"GIF89a\x01\0"), Some(ImageFormat::Gif));
+        assert_eq!(ImageFormat::detect(b"RIFF\0\0\0\0WEBPVP8 "), Some(ImageFormat::WebP));
+    }
+
+    #[test]
+    fn unknown_formats_arent_detected() {
+        assert_eq!(ImageFormat::detect(b""), None);
+        assert_eq!(ImageFormat::detect(b"GIF88a"), None);
+        assert_eq!(ImageFormat::detect(b"RIFF\0\0\0\0WAVE"), None);
+        // Too short to be a WebP image
+        assert_eq!(ImageFormat::detect(b"RIFF\0\0\0\0WEB"), None);
+        assert!(matches!(RgbaImage::from_bytes(b"text"), Err(ErrorKind::ImageError(_))));
+    }
+
+    #[test]
+    fn fit_modes_keep_aspect_ratio() {
+        assert_eq!(FitMode::Contain.size((200, 100), (50, 50)), (50, 25));
+        assert_eq!(FitMode::Cover.size((200, 100), (50, 50)), (100, 50));
+        assert_eq!(FitMode::Stretch.size((200, 100), (50, 50)), (50, 50));
+        assert_eq!(FitMode::None.size((200, 100), (50, 50)), (200, 100));
+        assert_eq!(FitMode::Contain.size((0, 100), (50, 50)), (0, 0));
+    }
+
+    #[test]
+    fn fit_rounding_stays_in_rectangle() {
+        // 3:1 into 10x10 makes 3.33 rows, which must not be rounded past the rectangle
+        // when containing, or left short of it when covering
+        assert_eq!(FitMode::Contain.size((30, 10), (10, 10)), (10, 3));
+        assert_eq!(FitMode::Cover.size((7, 3), (10, 10)), (23, 10));
+        assert_eq!(FitMode::Contain.size((1, 3), (10, 10)).1, 10);
+    }
+
+    #[test]
+    fn weights_add_up() {
+        for &filter in [Filter::Nearest, Filter::Bilinear, Filter::Area].iter() {
+            for &(src, dst) in [(10, 3), (3, 10), (7, 7), (1, 5)].iter() {
+                // Area weights are how much of each original pixel is covered, so they
+                // add up to the size of a scaled pixel
+                let expected = if filter == Filter::Area { src as f32 / dst as f32 } else { 1.0 };
+                for pixel in weights(src, dst, 0..dst, filter) {
+                    let total: f32 = pixel.iter().map(|&(_, weight)| weight).sum();
+                    assert!((total - expected).abs() < 1e-4, "{:?} {:?}", filter, pixel);
+                    assert!(pixel.iter().all(|&(i, _)| i < src));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn area_weights_cover_source() {
+        assert_eq!(weights(4, 2, 0..2, Filter::Area), vec![vec![(0, 1.0), (1, 1.0)], vec![(2, 1.0), (3, 1.0)]]);
+        assert_eq!(weights(4, 2, 0..2, Filter::Nearest), vec![vec![(1, 1.0)], vec![(3, 1.0)]]);
+    }
+
+    #[test]
+    fn resize_averages_pixels() {
+        let mut img = RgbaImage::new(2, 1);
+        img.set_pixel(0, 0, [255, 0, 0, 255]);
+        img.set_pixel(1, 0, [0, 0, 255, 255]);
+        assert_eq!(img.resize(1, 1, Filter::Area).get_pixel(0, 0), [128, 0, 128, 255]);
+        let big = img.resize(4, 2, Filter::Nearest);
+        assert_eq!((big.get_pixel(1, 1), big.get_pixel(2, 0)), ([255, 0, 0, 255], [0, 0, 255, 255]));
+    }
+
+    #[test]
+    fn resized_part_matches_whole() {
+        let mut img = RgbaImage::new(5, 9);
+        for (i, (x, y)) in (0..9).flat_map(|y| (0..5).map(move |x| (x, y))).enumerate() {
+            img.set_pixel(x, y, [(i * 7) as u8, (i * 13) as u8, (i * 29) as u8, 255 - i as u8]);
+        }
+        for &filter in [Filter::Nearest, Filter::Bilinear, Filter::Area].iter() {
+            let whole = img.resize(8, 30, filter);
+            let part = img.resize_part((8, 30), (2, 11, 4, 6), filter);
+            assert_eq!((part.width(), part.height()), (4, 6));
+            for (x, y) in (0..6).flat_map(|y| (0..4).map(move |x| (x, y))) {
+                assert_eq!(part.get_pixel(x, y), whole.get_pixel(x + 2, y + 11), "{:?}", filter);
+            }
+        }
+        // The part is cut off at the edges of the scaled image
+        let part = img.resize_part((8, 30), (6, 28, 4, 6), Filter::Nearest);
+        assert_eq!((part.width(), part.height()), (2, 2));
+    }
+
+    #[test]
+    fn transparent_pixels_dont_darken() {
+        let mut img = RgbaImage::new(2, 1);
+        img.set_pixel(0, 0, [255, 255, 255, 255]);
+        assert_eq!(img.resize(1, 1, Filter::Area).get_pixel(0, 0), [255, 255, 255, 128]);
+        assert_eq!(RgbaImage::new(2, 2).resize(1, 1, Filter::Bilinear).get_pixel(0, 0), [0; 4]);
+    }
+}
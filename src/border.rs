@@ -0,0 +1,196 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module holds the types used to draw boxes out of box-drawing characters.
+//! Boxes are drawn in cells instead of pixels, so their lines are as thin as the
+//! terminal can draw them.
+
+use crossterm::style::{ContentStyle, StyledContent};
+use crate::{boxes::{self, NONE, LIGHT, HEAVY, DOUBLE}, cell, Color, Layer, Rect, Result};
+
+/// The characters used to draw the lines of a box
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Lines {
+    /// Thin lines, like `┌─┐`
+    Single,
+
+    /// Two thin lines, like `╔═╗`
+    Double,
+
+    /// Thick lines, like `┏━┓`
+    Heavy,
+
+    /// Thin lines with rounded corners, like `╭─╮`
+    Rounded,
+
+    /// Plain ASCII, like `+-+`, for terminals and fonts without box-drawing characters
+    Ascii,
+}
+
+/// Where something goes in the space it has
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Align {
+    /// At the start (the left or top)
+    Start,
+
+    /// In the middle
+    Center,
+
+    /// At the end (the right or bottom)
+    End,
+}
+
+/// The color of each side of a box. Sides that are `None` use the layer's stroke color.
+/// The corners are the color of the top or bottom side.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BorderColors {
+    /// The color of the top side and its corners
+    pub top: Option<Color>,
+
+    /// The color of the right side
+    pub right: Option<Color>,
+
+    /// The color of the bottom side and its corners
+    pub bottom: Option<Color>,
+
+    /// The color of the left side
+    pub left: Option<Color>,
+}
+
+impl From<Color> for BorderColors {
+    fn from(color: Color) -> Self {
+        Self { top: Some(color), right: Some(color), bottom: Some(color), left: Some(color) }
+    }
+}
+
+/// How a box is drawn by `Layer::draw_box`
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BorderStyle {
+    /// The characters used to draw the lines
+    pub lines: Lines,
+
+    /// Text drawn on the top side of the box. It is cut short if it doesn't fit
+    pub title: Option<String>,
+
+    /// Where the title goes along the top side. Defaults to `Align::Start`
+    pub title_align: Align,
+
+    /// The colors of the sides
+    pub colors: BorderColors,
+}
+
+impl BorderStyle {
+    /// Creates a style with no title, using the layer's stroke color
+    pub fn new(lines: Lines) -> Self {
+        Self { lines, title: None, title_align: Align::Start, colors: BorderColors::default() }
+    }
+
+    /// Adds a title to the style
+    pub fn title(mut self, title: impl Into<String>, align: Align) -> Self {
+        self.title = Some(title.into());
+        self.title_align = align;
+        self
+    }
+
+    /// Sets the colors of the sides
+    pub fn colors(mut self, colors: impl Into<BorderColors>) -> Self {
+        self.colors = colors.into();
+        self
+    }
+}
+
+impl From<Lines> for BorderStyle {
+    fn from(lines: Lines) -> Self {
+        Self::new(lines)
+    }
+}
+
+impl<'a> Layer<'a> {
+    /// Draws the outline of a rectangle of cells (not pixels). Its lines join up with
+    /// other box-drawing characters on this layer and the layers under it, so boxes
+    /// can share sides to make tables.
+    pub fn draw_box(&mut self, rect: Rect, style: impl Into<BorderStyle>) -> Result<()> {
+        let style = style.into();
+        self.validate_cell_rect(rect)?;
+        if rect.width == 0 || rect.height == 0 {
+            return Ok(());
+        }
+        let stroke_color = self.state.stroke_color;
+        let color = |side: Option<Color>| side.unwrap_or(stroke_color);
+        let (right, bottom) = (rect.x + rect.width - 1, rect.y + rect.height - 1);
+        let weight = match style.lines {
+            Lines::Double => DOUBLE,
+            Lines::Heavy => HEAVY,
+            _ => LIGHT,
+        };
+
+        for y in rect.y..=bottom {
+            for x in rect.x..=right {
+                let on_side = [y == rect.y, x == right, y == bottom, x == rect.x];
+                if !on_side.contains(&true) {
+                    continue;
+                }
+                // The lines going up, right, down and left from the middle of the cell
+                let has_line = |along_side: bool, end: bool| if along_side && !end { weight } else { NONE };
+                let lines = [
+                    has_line(on_side[1] || on_side[3], y == rect.y),
+                    has_line(on_side[0] || on_side[2], x == right),
+                    has_line(on_side[1] || on_side[3], y == bottom),
+                    has_line(on_side[0] || on_side[2], x == rect.x),
+                ];
+                let c = match style.lines {
+                    Lines::Ascii => match lines {
+                        [NONE, _, NONE, _] if lines != [NONE; 4] => '-',
+                        [_, NONE, _, NONE] if lines != [NONE; 4] => '|',
+                        _ => '+',
+                    },
+                    Lines::Rounded => match boxes::from_lines(lines) {
+                        Some('┌') => '╭',
+                        Some('┐') => '╮',
+                        Some('└') => '╰',
+                        Some('┘') => '╯',
+                        c => c.unwrap_or('+'),
+                    },
+                    _ => boxes::from_lines(lines).unwrap_or('+'),
+                };
+                let side = if on_side[0] { style.colors.top }
+                    else if on_side[2] { style.colors.bottom }
+                    else if on_side[1] { style.colors.right }
+                    else { style.colors.left };
+                let mut cell_style = ContentStyle::new();
+                cell_style.foreground_color = Some(color(side));
+                self.set_cell((x, y), StyledContent::new(cell_style, c))?;
+            }
+        }
+
+        if let Some(title) = &style.title {
+            // The title has a space on each side, and doesn't cover the corners
+            let space = (rect.width as usize).saturating_sub(4);
+            let mut text = Vec::new();
+            let mut text_width = 0;
+            for c in title.chars().filter(|&c| cell::width(c) > 0) {
+                if text_width + cell::width(c) > space {
+                    break;
+                }
+                text_width += cell::width(c);
+                text.push(c);
+            }
+            if text.is_empty() {
+                return Ok(());
+            }
+            let start = match style.title_align {
+                Align::Start => 0,
+                Align::Center => (space - text_width) / 2,
+                Align::End => space - text_width,
+            };
+            let mut cell_style = ContentStyle::new();
+            cell_style.foreground_color = Some(color(style.colors.top));
+            let mut x = rect.x + 1 + start as u16;
+            for c in Some(' ').into_iter().chain(text).chain(Some(' ')) {
+                self.set_cell((x, rect.y), StyledContent::new(cell_style, c))?;
+                x += cell::width(c) as u16;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! Merges box-drawing characters, so that lines drawn on different layers join
+//! up when they are composited. For example, `│` over `─` makes `┼`.
+//!
+//! Each character is split into the lines going up, right, down and left from
+//! the middle of the cell, and each of those lines has a weight.
+
+/// There is no line going in this direction
+pub(crate) const NONE: u8 = 0;
+
+/// A thin line
+pub(crate) const LIGHT: u8 = 1;
+
+/// A thick line
+pub(crate) const HEAVY: u8 = 2;
+
+/// Two thin lines next to each other
+pub(crate) const DOUBLE: u8 = 3;
+
+const L: u8 = LIGHT;
+const H: u8 = HEAVY;
+const D: u8 = DOUBLE;
+const O: u8 = NONE;
+
+/// Box-drawing characters and the weights of their lines going up, right, down
+/// and left. Dashed lines and diagonals aren't here, since they can't be joined
+/// with anything. Where two characters have the same lines (such as `┌` and
+/// `╭`), the first one is used when a new character is made.
+const LINES: [(char, [u8; 4]); 113] = [
+    ('─', [O, L, O, L]), ('━', [O, H, O, H]), ('│', [L, O, L, O]), ('┃', [H, O, H, O]),
+    ('┌', [O, L, L, O]), ('┍', [O, H, L, O]), ('┎', [O, L, H, O]), ('┏', [O, H, H, O]),
+    ('┐', [O, O, L, L]), ('┑', [O, O, L, H]), ('┒', [O, O, H, L]), ('┓', [O, O, H, H]),
+    ('└', [L, L, O, O]), ('┕', [L, H, O, O]), ('┖', [H, L, O, O]), ('┗', [H, H, O, O]),
+    ('┘', [L, O, O, L]), ('┙', [L, O, O, H]), ('┚', [H, O, O, L]), ('┛', [H, O, O, H]),
+    ('├', [L, L, L, O]), ('┝', [L, H, L, O]), ('┞', [H, L, L, O]), ('┟', [L, L, H, O]),
+    ('┠', [H, L, H, O]), ('┡', [H, H, L, O]), ('┢', [L, H, H, O]), ('┣', [H, H, H, O]),
+    ('┤', [L, O, L, L]), ('┥', [L, O, L, H]), ('┦', [H, O, L, L]), ('┧', [L, O, H, L]),
+    ('┨', [H, O, H, L]), ('┩', [H, O, L, H]), ('┪', [L, O, H, H]), ('┫', [H, O, H, H]),
+    ('┬', [O, L, L, L]), ('┭', [O, L, L, H]), ('┮', [O, H, L, L]), ('┯', [O, H, L, H]),
+    ('┰', [O, L, H, L]), ('┱', [O, L, H, H]), ('┲', [O, H, H, L]), ('┳', [O, H, H, H]),
+    ('┴', [L, L, O, L]), ('┵', [L, L, O, H]), ('┶', [L, H, O, L]), ('┷', [L, H, O, H]),
+    ('┸', [H, L, O, L]), ('┹', [H, L, O, H]), ('┺', [H, H, O, L]), ('┻', [H, H, O, H]),
+    ('┼', [L, L, L, L]), ('┽', [L, L, L, H]), ('┾', [L, H, L, L]), ('┿', [L, H, L, H]),
+    ('╀', [H, L, L, L]), ('╁', [L, L, H, L]), ('╂', [H, L, H, L]), ('╃', [H, L, L, H]),
+    ('╄', [H, H, L, L]), ('╅', [L, L, H, H]), ('╆', [L, H, H, L]), ('╇', [H, H, L, H]),
+    ('╈', [L, H, H, H]), ('╉', [H, L, H, H]), ('╊', [H, H, H, L]), ('╋', [H, H, H, H]),
+    ('═', [O, D, O, D]), ('║', [D, O, D, O]), ('╒', [O, D, L, O]), ('╓', [O, L, D, O]),
+    ('╔', [O, D, D, O]), ('╕', [O, O, L, D]), ('╖', [O, O, D, L]), ('╗', [O, O, D, D]),
+    ('╘', [L, D, O, O]), ('╙', [D, L, O, O]), ('╚', [D, D, O, O]), ('╛', [L, O, O, D]),
+    ('╜', [D, O, O, L]), ('╝', [D, O, O, D]), ('╞', [L, D, L, O]), ('╟', [D, L, D, O]),
+    ('╠', [D, D, D, O]), ('╡', [L, O, L, D]), ('╢', [D, O, D, L]), ('╣', [D, O, D, D]),
+    ('╤', [O, D, L, D]), ('╥', [O, L, D, L]), ('╦', [O, D, D, D]), ('╧', [L, D, O, D]),
+    ('╨', [D, L, O, L]), ('╩', [D, D, O, D]), ('╪', [L, D, L, D]), ('╫', [D, L, D, L]),
+    ('╬', [D, D, D, D]), ('╭', [O, L, L, O]), ('╮', [O, O, L, L]), ('╯', [L, O, O, L]),
+    ('╰', [L, L, O, O]), ('╴', [O, O, O, L]), ('╵', [L, O, O, O]), ('╶', [O, L, O, O]),
+    ('╷', [O, O, L, O]), ('╸', [O, O, O, H]), ('╹', [H, O, O, O]), ('╺', [O, H, O, O]),
+    ('╻', [O, O, H, O]), ('╼', [O, H, O, L]), ('╽', [L, O, H, O]), ('╾', [O, L, O, H]),
+    ('╿', [H, O, L, O]),
+];
+
+/// Gets the weights of the lines going up, right, down and left in a box-drawing
+/// character, or `None` if it isn't one that can be merged
+pub(crate) fn lines(c: char) -> Option<[u8; 4]> {
+    LINES.iter().find(|(line, _)| *line == c).map(|(_, weights)| *weights)
+}
+
+/// Gets the box-drawing character with these lines, if there is one
+pub(crate) fn from_lines(weights: [u8; 4]) -> Option<char> {
+    LINES.iter().find(|(_, w)| *w == weights).map(|(c, _)| *c)
+}
+
+/// Merges the lines of `above` with the lines of `below`. Where both have a line
+/// going the same way, the weight from `above` is used. `None` is returned if
+/// either character isn't a box-drawing character.
+pub(crate) fn merge(below: char, above: char) -> Option<char> {
+    let (lower, upper) = (lines(below)?, lines(above)?);
+    let mut merged = upper;
+    for (weight, &under) in merged.iter_mut().zip(lower.iter()) {
+        if *weight == NONE {
+            *weight = under;
+        }
+    }
+    // Keep the characters as they are if nothing was added, so that rounded
+    // corners stay rounded
+    if merged == upper {
+        return Some(above);
+    }
+    if merged == lower {
+        return Some(below);
+    }
+    from_lines(merged)
+        .or_else(|| {
+            // There aren't characters for every mix of weights (there are none with
+            // both heavy and double lines), so if this one doesn't exist, every line
+            // is drawn with the heaviest weight from `above`
+            let weight = upper.iter().copied().max().unwrap_or(LIGHT);
+            from_lines(merged.map(|w| if w == NONE { NONE } else { weight }))
+        })
+        .or(Some(above))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crossing_lines_merge() {
+        assert_eq!(merge('│', '─'), Some('┼'));
+        assert_eq!(merge('─', '│'), Some('┼'));
+        assert_eq!(merge('┌', '┘'), Some('┼'));
+        assert_eq!(merge('║', '═'), Some('╬'));
+    }
+
+    #[test]
+    fn upper_weight_wins() {
+        assert_eq!(merge('─', '━'), Some('━'));
+        assert_eq!(merge('━', '─'), Some('─'));
+        assert_eq!(merge('┃', '─'), Some('╂'));
+    }
+
+    #[test]
+    fn unchanged_characters_are_kept() {
+        assert_eq!(merge('╭', '╭'), Some('╭'));
+        assert_eq!(merge('╶', '╰'), Some('╰'));
+        assert_eq!(merge('╰', '╶'), Some('╰'));
+    }
+
+    #[test]
+    fn missing_mix_uses_heaviest_upper_weight() {
+        assert_eq!(merge('═', '┃'), Some('╋'));
+    }
+
+    #[test]
+    fn other_characters_dont_merge() {
+        assert_eq!(merge('a', '─'), None);
+        assert_eq!(merge('─', ' '), None);
+    }
+
+    #[test]
+    fn lines_match_table() {
+        for &(c, weights) in LINES.iter() {
+            assert_eq!(lines(c), Some(weights));
+            assert_eq!(from_lines(weights).and_then(lines), Some(weights));
+        }
+    }
+}
@@ -0,0 +1,218 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! Converts between the characters stored in a layer's buffer and the pixels
+//! they represent.
+//!
+//! Every cell is treated as a 2x2 grid of quadrants. A block character says which
+//! quadrants are drawn with its foreground color, and the rest use the background
+//! color. Depending on `PX_SIZE`, a pixel covers 1, 2 or 4 quadrants.
+
+use crossterm::style::{ContentStyle, StyledContent};
+use unicode_width::UnicodeWidthChar;
+use crate::{Color, IPoint, PX_SIZE};
+
+/// Block characters indexed by which quadrants they fill. The bits are, from
+/// lowest to highest, top left, top right, bottom left and bottom right.
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█'
+];
+
+/// Stored in the cell after a wide character, since the wide character covers
+/// that cell too. It is never printed.
+pub(crate) const CONTINUATION: char = '\0';
+
+/// The number of pixels in a cell horizontally and vertically
+pub(crate) fn px_per_cell() -> (u16, u16) {
+    ((1.0 / PX_SIZE.0).round() as u16, (1.0 / PX_SIZE.1).round() as u16)
+}
+
+/// Gets the index of the cell a pixel is in, and the index of the pixel within
+/// that cell
+pub(crate) fn locate(p: IPoint, cols: u16) -> (usize, usize) {
+    let (cw, ch) = px_per_cell();
+    let cell = (p.1 / ch) as usize * cols as usize + (p.0 / cw) as usize;
+    let sub = ((p.1 % ch) * cw + p.0 % cw) as usize;
+    (cell, sub)
+}
+
+/// Whether the character is made up of (up to two colors of) quadrants
+pub(crate) fn is_block(c: char) -> bool {
+    QUADRANTS.contains(&c)
+}
+
+/// How many cells a character takes up. This is 2 for wide characters, such as
+/// CJK characters and most emoji, and 0 for characters that can't go in a cell on
+/// their own, such as combining marks and control characters.
+pub(crate) fn width(c: char) -> usize {
+    if c == CONTINUATION {
+        0
+    }
+    else {
+        c.width().unwrap_or(0)
+    }
+}
+
+/// Whether the cell holds a wide character, which covers the next cell too
+pub(crate) fn is_wide(c: &StyledContent<char>) -> bool {
+    width(*c.content()) == 2
+}
+
+/// Gets an empty cell with the same color behind it
+pub(crate) fn blank(c: &StyledContent<char>) -> StyledContent<char> {
+    let mut style = ContentStyle::new();
+    style.background_color = background(c);
+    StyledContent::new(style, ' ')
+}
+
+/// Before cell `i` of a buffer is overwritten, this blanks the other half of the
+/// wide character it is part of (if it is part of one), so that half of a wide
+/// character isn't left behind. Returns the index of the cell that was blanked.
+/// `i` has to be in the buffer.
+pub(crate) fn split_wide(buf: &mut [StyledContent<char>], i: usize, cols: u16) -> Option<usize> {
+    let col = i % cols as usize;
+    let other = if *buf[i].content() == CONTINUATION && col > 0 && is_wide(&buf[i - 1]) {
+        i - 1
+    }
+    else if is_wide(&buf[i]) && col + 1 < cols as usize && buf.get(i + 1).map(|c| *c.content()) == Some(CONTINUATION) {
+        i + 1
+    }
+    else {
+        return None;
+    };
+    buf[other] = blank(&buf[other]);
+    Some(other)
+}
+
+/// Gets the color behind a cell, which is kept when text is drawn over it. A full
+/// block is all foreground, so its foreground color is used.
+pub(crate) fn background(c: &StyledContent<char>) -> Option<Color> {
+    let style = c.style();
+    if *c.content() == '█' {
+        style.foreground_color.or(style.background_color)
+    }
+    else {
+        style.background_color
+    }
+}
+
+/// Gets the colors of the pixels in a cell. Pixels are in row-major order, and
+/// entries past the number of pixels in a cell are `None`. A character that
+/// isn't a block character is treated as being filled with its background color.
+pub(crate) fn decode(c: &StyledContent<char>) -> [Option<Color>; 4] {
+    let style = c.style();
+    let mask = QUADRANTS.iter().position(|q| q == c.content()).unwrap_or(0);
+    let quadrant = |i: usize| if mask & (1 << i) != 0 { style.foreground_color } else { style.background_color };
+    let (cw, ch) = px_per_cell();
+    let mut px = [None; 4];
+    for y in 0..ch {
+        for x in 0..cw {
+            px[(y * cw + x) as usize] = quadrant((y * 2 / ch * 2 + x * 2 / cw) as usize);
+        }
+    }
+    px
+}
+
+/// Creates the character that best represents the pixels in a cell, in the same
+/// order as they are returned from `decode`.
+///
+/// A character can only have two colors, so if there are more, the extra ones
+/// are replaced with the background color. The same goes for transparent pixels
+/// when there are two colors.
+pub(crate) fn encode(px: &[Option<Color>; 4]) -> StyledContent<char> {
+    let (cw, ch) = px_per_cell();
+    let mut quadrants = [None; 4];
+    for (i, q) in quadrants.iter_mut().enumerate() {
+        let (x, y) = (i as u16 % 2, i as u16 / 2);
+        *q = px[(y * ch / 2 * cw + x * cw / 2) as usize];
+    }
+    let fg = quadrants.iter().find_map(|&q| q);
+    let bg = quadrants.iter().find_map(|&q| q.filter(|&q| Some(q) != fg));
+    let mut mask = 0;
+    for (i, &q) in quadrants.iter().enumerate() {
+        if q.is_some() && q == fg {
+            mask |= 1 << i;
+        }
+    }
+    let mut style = ContentStyle::new();
+    if mask != 0 {
+        style.foreground_color = fg;
+        style.background_color = bg;
+    }
+    StyledContent::new(style, QUADRANTS[mask])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(c: char) -> StyledContent<char> {
+        StyledContent::new(ContentStyle::new(), c)
+    }
+
+    /// The number of pixels in a cell
+    fn count() -> usize {
+        let (cw, ch) = px_per_cell();
+        (cw * ch) as usize
+    }
+
+    #[test]
+    fn locate_finds_cell_and_pixel() {
+        let (cw, ch) = px_per_cell();
+        assert_eq!(locate((0, 0), 10), (0, 0));
+        assert_eq!(locate((3 * cw, 5 * ch + ch - 1), 10), (53, ((ch - 1) * cw) as usize));
+        assert_eq!(locate((9 * cw + cw - 1, 0), 10), (9, (cw - 1) as usize));
+    }
+
+    #[test]
+    fn two_colors_round_trip() {
+        let mut px = [None; 4];
+        for (i, p) in px.iter_mut().take(count()).enumerate() {
+            *p = Some(if i % 2 == 0 { Color::Red } else { Color::Blue });
+        }
+        assert_eq!(decode(&encode(&px)), px);
+        let mut one = [None; 4];
+        one[0] = Some(Color::Green);
+        assert_eq!(decode(&encode(&one)), one);
+    }
+
+    #[test]
+    fn extra_colors_become_background() {
+        let mut px = [Some(Color::Red), Some(Color::Blue), None, None];
+        let (mut expected, last) = (px, count() - 1);
+        if last > 1 {
+            px[last] = Some(Color::Green);
+            expected[last] = Some(Color::Blue);
+        }
+        assert_eq!(decode(&encode(&px)), expected);
+    }
+
+    #[test]
+    fn text_decodes_as_background() {
+        let mut c = cell('a');
+        c.style_mut().foreground_color = Some(Color::Red);
+        c.style_mut().background_color = Some(Color::Blue);
+        let px = decode(&c);
+        assert!(px[..count()].iter().all(|&p| p == Some(Color::Blue)));
+        let empty = encode(&[None; 4]);
+        assert_eq!((*empty.content(), empty.style().background_color), (' ', None));
+    }
+
+    #[test]
+    fn wide_characters_are_split() {
+        let mut buf = vec![cell('中'), cell(CONTINUATION), cell('a')];
+        assert_eq!(split_wide(&mut buf, 1, 3), Some(0));
+        assert_eq!(*buf[0].content(), ' ');
+        let mut buf = vec![cell('中'), cell(CONTINUATION), cell('a')];
+        assert_eq!(split_wide(&mut buf, 0, 3), Some(1));
+        assert_eq!(split_wide(&mut buf, 2, 3), None);
+    }
+
+    #[test]
+    fn wide_character_at_end_has_no_continuation() {
+        let mut buf = vec![cell('a'), cell('中')];
+        assert_eq!(split_wide(&mut buf, 1, 2), None);
+        assert_eq!(split_wide(&mut buf, 1, 3), None);
+        assert_eq!((width('中'), width('a'), width(CONTINUATION)), (2, 1, 0));
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! Color conversions used when colors need to be mixed together
+
+use crate::Color;
+
+/// The RGB values of the 16 standard colors, as used by xterm
+const STANDARD: [(u8, u8, u8); 16] = [
+    (0, 0, 0), (128, 0, 0), (0, 128, 0), (128, 128, 0),
+    (0, 0, 128), (128, 0, 128), (0, 128, 128), (192, 192, 192),
+    (128, 128, 128), (255, 0, 0), (0, 255, 0), (255, 255, 0),
+    (0, 0, 255), (255, 0, 255), (0, 255, 255), (255, 255, 255),
+];
+
+/// Gets the RGB values of a color. The named colors and ANSI values are
+/// converted using xterm's default palette. `Color::Reset` depends on the
+/// terminal, so it doesn't have RGB values.
+pub(crate) fn to_rgb(color: Color) -> Option<(u8, u8, u8)> {
+    use Color::*;
+
+    let ansi = match color {
+        Reset => return None,
+        Rgb { r, g, b } => return Some((r, g, b)),
+        AnsiValue(n) => n,
+        Black => 0,
+        DarkRed => 1,
+        DarkGreen => 2,
+        DarkYellow => 3,
+        DarkBlue => 4,
+        DarkMagenta => 5,
+        DarkCyan => 6,
+        Grey => 7,
+        DarkGrey => 8,
+        Red => 9,
+        Green => 10,
+        Yellow => 11,
+        Blue => 12,
+        Magenta => 13,
+        Cyan => 14,
+        White => 15,
+    };
+    Some(match ansi {
+        0..=15 => STANDARD[ansi as usize],
+        16..=231 => {
+            let level = |n: u8| if n == 0 { 0 } else { 55 + n * 40 };
+            let n = ansi - 16;
+            (level(n / 36), level(n / 6 % 6), level(n % 6))
+        },
+        _ => {
+            let grey = 8 + (ansi - 232) * 10;
+            (grey, grey, grey)
+        }
+    })
+}
+
+/// Mixes two colors, where `t` is how much of `b` to use, from 0 to 1. If either
+/// color doesn't have RGB values, whichever one there is more of is used.
+pub(crate) fn mix(a: Color, b: Color, t: f32) -> Color {
+    match (to_rgb(a), to_rgb(b)) {
+        (Some(a), Some(b)) => {
+            let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+            Color::Rgb { r: channel(a.0, b.0), g: channel(a.1, b.1), b: channel(a.2, b.2) }
+        },
+        _ => if t < 0.5 { a } else { b },
+    }
+}
+
+/// Whether two colors are the same, give or take `tolerance` in each of their RGB
+/// values. `None` (a transparent pixel) is only similar to itself.
+pub(crate) fn similar(a: Option<Color>, b: Option<Color>, tolerance: u8) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) if a == b => true,
+        (Some(a), Some(b)) => match (to_rgb(a), to_rgb(b)) {
+            (Some(a), Some(b)) => {
+                let close = |a: u8, b: u8| (a as i16 - b as i16).abs() <= tolerance as i16;
+                close(a.0, b.0) && close(a.1, b.1) && close(a.2, b.2)
+            },
+            _ => false,
+        },
+        (None, None) => true,
+        _ => false,
+    }
+}
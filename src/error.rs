@@ -27,6 +27,15 @@ pub enum ErrorKind {
     /// An attempt was made to plot a point out of bounds
     InvalidPoint(f32, f32),
 
+    /// A font file couldn't be read, because it isn't in the right format
+    FontError(String),
+
+    /// An image couldn't be decoded, because it is invalid or its format isn't supported
+    ImageError(String),
+
+    /// A sprite sheet's frames are invalid, or a frame that isn't in it was drawn
+    SpriteError(String),
+
     /// An error originating from borrowing a `RefCell`
     BorrowError,
 
@@ -45,6 +54,9 @@ impl fmt::Display for ErrorKind {
             IOError(err) => write!(f, "{}", err),
             BmpError(err) => write!(f, "{}", err),
             InvalidPoint(x, y) => write!(f, "Invalid Point: ({}, {})", x, y),
+            FontError(msg) => write!(f, "Invalid font: {}", msg),
+            ImageError(msg) => write!(f, "Invalid image: {}", msg),
+            SpriteError(msg) => write!(f, "Invalid sprite sheet: {}", msg),
             BorrowError => write!(f, "already mutably borrowed"),
             PoisonError => write!(f, "poisoned lock: another task failed inside"),
         }
@@ -0,0 +1,68 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! Flood filling, which fills the area around a point that is all one color
+
+use crate::{color, paint::{IntoPaint, Shader}, Color, IPoint, Layer, Result};
+
+impl<'a> Layer<'a> {
+    /// Fills the area connected to `p` that is the same color as it, give or take
+    /// `tolerance` in each RGB value. Transparent areas can be filled too. The fill
+    /// doesn't go outside of the clip rectangle. If `paint` is `None`, the current
+    /// fill paint is used.
+    pub fn flood_fill(&mut self, p: IPoint, paint: impl IntoPaint, tolerance: u8) -> Result<()> {
+        let paint = self.fill_paint(paint);
+        let shader = Shader::new(&paint, &self.state.transform);
+        self.validate_i_points(&[p])?;
+        let (width, height) = self.resolution();
+        // Reading the whole layer at once is a lot faster than locking it for every pixel
+        let pixels: Vec<Option<Color>> = self.pixels().map(|(_, color)| color).collect();
+        let target = pixels[p.1 as usize * width + p.0 as usize];
+        let clip = self.state.clip;
+        let mut filled = vec![false; pixels.len()];
+        let fillable = |filled: &[bool], x: usize, y: usize| {
+            let i = y * width + x;
+            let in_clip = match clip {
+                Some(clip) => clip.contains((x as u16, y as u16)),
+                None => true,
+            };
+            !filled[i] && in_clip && color::similar(pixels[i], target, tolerance)
+        };
+
+        // This fills a row at a time, and only remembers one pixel for each run of
+        // pixels above or below that row that still need to be filled, so it uses
+        // far less memory than filling one pixel at a time
+        let mut seeds = vec![(p.0 as usize, p.1 as usize)];
+        while let Some((x, y)) = seeds.pop() {
+            if !fillable(&filled, x, y) {
+                continue;
+            }
+            let mut left = x;
+            while left > 0 && fillable(&filled, left - 1, y) {
+                left -= 1;
+            }
+            let mut right = x;
+            while right + 1 < width && fillable(&filled, right + 1, y) {
+                right += 1;
+            }
+            for x in left..=right {
+                filled[y * width + x] = true;
+                self.plot_shaded((x as u16, y as u16), &shader)?;
+            }
+            let rows = [y.checked_sub(1), Some(y + 1).filter(|&y| y < height)];
+            for &row in rows.iter() {
+                if let Some(row) = row {
+                    let mut in_run = false;
+                    for x in left..=right {
+                        let can_fill = fillable(&filled, x, row);
+                        if can_fill && !in_run {
+                            seeds.push((x, row));
+                        }
+                        in_run = can_fill;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
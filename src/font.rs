@@ -0,0 +1,469 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module holds bitmap fonts, which are used to draw text in pixels instead
+//! of cells, so that it can be bigger than the terminal's font.
+
+use std::{collections::HashMap, io::Read, str::SplitWhitespace};
+use lazy_static::lazy_static;
+use crate::{error::ErrorKind, Color, IPoint, Layer, Result};
+
+/// The glyphs of the built-in font, for the printable ASCII characters from `' '`
+/// to `'~'`. Each glyph is 5 columns, and bit 0 of each column is the top row.
+const SMALL_GLYPHS: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00], [0x00, 0x00, 0x5F, 0x00, 0x00], [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7F, 0x14, 0x7F, 0x14], [0x24, 0x2A, 0x7F, 0x2A, 0x12], [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50], [0x00, 0x00, 0x07, 0x00, 0x00], [0x00, 0x1C, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1C, 0x00], [0x14, 0x08, 0x3E, 0x08, 0x14], [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00], [0x08, 0x08, 0x08, 0x08, 0x08], [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02], [0x3E, 0x51, 0x49, 0x45, 0x3E], [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46], [0x21, 0x41, 0x45, 0x4B, 0x31], [0x18, 0x14, 0x12, 0x7F, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39], [0x3C, 0x4A, 0x49, 0x49, 0x30], [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36], [0x06, 0x49, 0x49, 0x29, 0x1E], [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00], [0x08, 0x14, 0x22, 0x41, 0x00], [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08], [0x02, 0x01, 0x51, 0x09, 0x06], [0x32, 0x49, 0x79, 0x41, 0x3E],
+    [0x7E, 0x11, 0x11, 0x11, 0x7E], [0x7F, 0x49, 0x49, 0x49, 0x36], [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C], [0x7F, 0x49, 0x49, 0x49, 0x41], [0x7F, 0x09, 0x09, 0x09, 0x01],
+    [0x3E, 0x41, 0x49, 0x49, 0x7A], [0x7F, 0x08, 0x08, 0x08, 0x7F], [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01], [0x7F, 0x08, 0x14, 0x22, 0x41], [0x7F, 0x40, 0x40, 0x40, 0x40],
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F], [0x7F, 0x04, 0x08, 0x10, 0x7F], [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06], [0x3E, 0x41, 0x51, 0x21, 0x5E], [0x7F, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31], [0x01, 0x01, 0x7F, 0x01, 0x01], [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F], [0x3F, 0x40, 0x38, 0x40, 0x3F], [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x07, 0x08, 0x70, 0x08, 0x07], [0x61, 0x51, 0x49, 0x45, 0x43], [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20], [0x00, 0x41, 0x41, 0x7F, 0x00], [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40], [0x00, 0x01, 0x02, 0x04, 0x00], [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7F, 0x48, 0x44, 0x44, 0x38], [0x38, 0x44, 0x44, 0x44, 0x20], [0x38, 0x44, 0x44, 0x48, 0x7F],
+    [0x38, 0x54, 0x54, 0x54, 0x18], [0x08, 0x7E, 0x09, 0x01, 0x02], [0x0C, 0x52, 0x52, 0x52, 0x3E],
+    [0x7F, 0x08, 0x04, 0x04, 0x78], [0x00, 0x44, 0x7D, 0x40, 0x00], [0x20, 0x40, 0x44, 0x3D, 0x00],
+    [0x7F, 0x10, 0x28, 0x44, 0x00], [0x00, 0x41, 0x7F, 0x40, 0x00], [0x7C, 0x04, 0x18, 0x04, 0x78],
+    [0x7C, 0x08, 0x04, 0x04, 0x78], [0x38, 0x44, 0x44, 0x44, 0x38], [0x7C, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7C], [0x7C, 0x08, 0x04, 0x04, 0x08], [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20], [0x3C, 0x40, 0x40, 0x20, 0x7C], [0x1C, 0x20, 0x40, 0x20, 0x1C],
+    [0x3C, 0x40, 0x30, 0x40, 0x3C], [0x44, 0x28, 0x10, 0x28, 0x44], [0x0C, 0x50, 0x50, 0x50, 0x3C],
+    [0x44, 0x64, 0x54, 0x4C, 0x44], [0x00, 0x08, 0x36, 0x41, 0x00], [0x00, 0x00, 0x7F, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00], [0x10, 0x08, 0x08, 0x10, 0x08],
+];
+
+lazy_static! {
+    static ref SMALL: Font = {
+        let mut glyphs = HashMap::new();
+        for (i, columns) in SMALL_GLYPHS.iter().enumerate() {
+            let mut bits = vec![false; 5 * 7];
+            for (x, column) in columns.iter().enumerate() {
+                for y in 0..7 {
+                    bits[y * 5 + x] = column & (1 << y) != 0;
+                }
+            }
+            glyphs.insert((b' ' + i as u8) as char, Glyph { width: 5, height: 7, offset: (0, -7), advance: 6, bits });
+        }
+        Font { glyphs, ascent: 7, descent: 1, default_char: '?' }
+    };
+}
+
+/// The picture of one character in a font
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Glyph {
+    width: u16,
+    height: u16,
+
+    /// Where the top left corner of the glyph is, compared to where the pen is on
+    /// the baseline
+    offset: (i32, i32),
+
+    /// How far the pen moves right after drawing the glyph
+    advance: u16,
+
+    /// Whether each pixel is drawn, going left to right, then top to bottom
+    bits: Vec<bool>,
+}
+
+impl Glyph {
+    /// Gets the pixels that are drawn, compared to where the pen is
+    fn pixels(&self) -> impl Iterator<Item = (i32, i32)> + '_ {
+        let width = self.width as usize;
+        self.bits.iter().enumerate()
+            .filter(|(_, &on)| on)
+            .map(move |(i, _)| (self.offset.0 + (i % width) as i32, self.offset.1 + (i / width) as i32))
+    }
+}
+
+/// A font made of pixels, used by `Layer::draw_text_px`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Font {
+    glyphs: HashMap<char, Glyph>,
+    ascent: u16,
+    descent: u16,
+    default_char: char,
+}
+
+impl Font {
+    /// Gets the built-in font, which has 5x7 glyphs for the printable ASCII characters
+    pub fn small() -> &'static Font {
+        &SMALL
+    }
+
+    /// Reads a font in the Glyph Bitmap Distribution Format (BDF). Characters are
+    /// assumed to be encoded as Unicode code points, and ones with no encoding are
+    /// left out.
+    pub fn from_bdf(src: &mut impl Read) -> Result<Self> {
+        let mut text = String::new();
+        src.read_to_string(&mut text)?;
+        let mut lines = text.lines();
+        let mut glyphs = HashMap::new();
+        let mut bounding_box = [0; 4];
+        let (mut ascent, mut descent, mut default_char) = (None, None, None);
+        while let Some(line) = lines.next() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("FONTBOUNDINGBOX") => bounding_box.copy_from_slice(&numbers(words, 4)?),
+                Some("FONT_ASCENT") => ascent = Some(numbers(words, 1)?[0]),
+                Some("FONT_DESCENT") => descent = Some(numbers(words, 1)?[0]),
+                Some("DEFAULT_CHAR") => default_char = std::char::from_u32(numbers(words, 1)?[0] as u32),
+                Some("STARTCHAR") => {
+                    let (encoding, glyph) = bdf_glyph(&mut lines, bounding_box)?;
+                    if let Some(c) = encoding.and_then(|n| std::char::from_u32(n as u32)) {
+                        glyphs.insert(c, glyph);
+                    }
+                },
+                _ => {},
+            }
+        }
+        if glyphs.is_empty() {
+            return Err(ErrorKind::FontError("BDF font has no glyphs".to_string()));
+        }
+        // Without FONT_ASCENT and FONT_DESCENT, the bounding box of the font is used
+        let [_, height, _, bottom] = bounding_box;
+        Ok(Self {
+            glyphs,
+            ascent: ascent.unwrap_or(height.saturating_add(bottom)).clamp(0, u16::MAX as i32) as u16,
+            descent: descent.unwrap_or(bottom.saturating_neg()).clamp(0, u16::MAX as i32) as u16,
+            default_char: default_char.unwrap_or('?'),
+        })
+    }
+
+    /// Reads a PC Screen Font (PSF), version 1 or 2, like the ones used by the Linux
+    /// console. If the font has no Unicode table, glyph `n` is used for the character
+    /// with code point `n`. PSF fonts don't say where their baseline is, so it is put
+    /// at the bottom of the glyphs.
+    pub fn from_psf(src: &mut impl Read) -> Result<Self> {
+        let mut data = Vec::new();
+        src.read_to_end(&mut data)?;
+        let invalid = |msg: &str| ErrorKind::FontError(msg.to_string());
+        let word = |i: usize| data.get(i..i + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]) as usize);
+
+        let (version, width, height, count, glyph_size, header_size, has_table) = if data.starts_with(&[0x36, 0x04]) && data.len() >= 4 {
+            let mode = data[2];
+            let size = data[3] as usize;
+            (1, 8, size, if mode & 0x01 != 0 { 512 } else { 256 }, size, 4, mode & 0x06 != 0)
+        }
+        else if data.starts_with(&[0x72, 0xb5, 0x4a, 0x86]) {
+            let header = (word(8), word(12), word(16), word(20), word(24), word(28));
+            match header {
+                (Some(header_size), Some(flags), Some(count), Some(size), Some(height), Some(width)) =>
+                    (2, width, height, count, size, header_size, flags & 0x01 != 0),
+                _ => return Err(invalid("PSF header is cut off")),
+            }
+        }
+        else {
+            return Err(invalid("not a PSF file"));
+        };
+        if width == 0 || height == 0 || width > u16::MAX as usize || height > u16::MAX as usize {
+            return Err(invalid("PSF glyphs have an invalid size"));
+        }
+        let row_size = width.div_ceil(8);
+        match row_size.checked_mul(height) {
+            Some(bitmap_size) if bitmap_size <= glyph_size => {},
+            _ => return Err(invalid("PSF glyphs are bigger than the space for them")),
+        }
+        // The sizes come from the file, so the glyphs are checked to fit in it before
+        // anything is allocated for them
+        if header_size > data.len() || count > (data.len() - header_size) / glyph_size {
+            return Err(invalid("PSF glyphs are cut off"));
+        }
+        let table_start = header_size + count * glyph_size;
+
+        let mut glyph_list = Vec::with_capacity(count);
+        for i in 0..count {
+            let bytes = &data[header_size + i * glyph_size..];
+            let mut bits = vec![false; width * height];
+            for y in 0..height {
+                for x in 0..width {
+                    bits[y * width + x] = bytes[y * row_size + x / 8] & (0x80 >> (x % 8)) != 0;
+                }
+            }
+            glyph_list.push(Glyph {
+                width: width as u16,
+                height: height as u16,
+                offset: (0, -(height as i32)),
+                advance: width as u16,
+                bits,
+            });
+        }
+
+        let mut glyphs = HashMap::new();
+        if has_table {
+            let mut table = &data[table_start..];
+            for glyph in glyph_list {
+                // Each glyph's entry is a list of characters, then (optionally) a list of
+                // sequences of characters, which are left out, then a terminator
+                let chars = if version == 1 {
+                    let mut chars = Vec::new();
+                    while table.len() >= 2 {
+                        let n = u16::from_le_bytes([table[0], table[1]]);
+                        table = &table[2..];
+                        match n {
+                            0xFFFF => break,
+                            0xFFFE => chars.push(None),
+                            n => chars.push(std::char::from_u32(n as u32)),
+                        }
+                    }
+                    chars.into_iter().take_while(Option::is_some).flatten().collect::<Vec<_>>()
+                }
+                else {
+                    let end = table.iter().position(|&b| b == 0xFF).unwrap_or(table.len());
+                    let entry = &table[..end];
+                    table = &table[(end + 1).min(table.len())..];
+                    let singles = &entry[..entry.iter().position(|&b| b == 0xFE).unwrap_or(entry.len())];
+                    String::from_utf8_lossy(singles).chars().collect()
+                };
+                for c in chars {
+                    glyphs.insert(c, glyph.clone());
+                }
+            }
+        }
+        else {
+            for (i, glyph) in glyph_list.into_iter().enumerate() {
+                if let Some(c) = std::char::from_u32(i as u32) {
+                    glyphs.insert(c, glyph);
+                }
+            }
+        }
+        Ok(Self { glyphs, ascent: height as u16, descent: 0, default_char: '?' })
+    }
+
+    /// The distance, in pixels, from the top of a line to its baseline
+    pub fn ascent(&self) -> u16 {
+        self.ascent
+    }
+
+    /// The distance, in pixels, from the top of one line to the top of the next
+    pub fn line_height(&self) -> u16 {
+        self.ascent.saturating_add(self.descent)
+    }
+
+    /// Gets the width, in pixels, of the widest line of some text
+    pub fn text_width(&self, text: &str) -> u16 {
+        text.split('\n')
+            .map(|line| line.chars().filter_map(|c| self.glyph(c)).map(|g| g.advance as u32).sum::<u32>())
+            .max()
+            .unwrap_or(0)
+            .min(u16::MAX as u32) as u16
+    }
+
+    /// Gets the width and height, in pixels, of some text
+    pub fn measure(&self, text: &str) -> (u16, u16) {
+        let lines = text.split('\n').count() as u32;
+        (self.text_width(text), (lines * self.line_height() as u32).min(u16::MAX as u32) as u16)
+    }
+
+    /// Whether the font has a glyph for a character
+    pub fn has_glyph(&self, c: char) -> bool {
+        self.glyphs.contains_key(&c)
+    }
+
+    /// Gets the glyph for a character, or for the default character if the font doesn't
+    /// have one for it
+    fn glyph(&self, c: char) -> Option<&Glyph> {
+        self.glyphs.get(&c).or_else(|| self.glyphs.get(&self.default_char))
+    }
+}
+
+/// Reads `count` numbers after a keyword in a BDF file
+fn numbers(words: SplitWhitespace<'_>, count: usize) -> Result<Vec<i32>> {
+    let out: Vec<i32> = words.take(count).filter_map(|word| word.parse().ok()).collect();
+    if out.len() < count {
+        return Err(ErrorKind::FontError("expected a number".to_string()));
+    }
+    Ok(out)
+}
+
+/// Reads one glyph of a BDF file, after its STARTCHAR line, and returns it with
+/// its encoding
+fn bdf_glyph<'a>(lines: &mut impl Iterator<Item = &'a str>, bounding_box: [i32; 4]) -> Result<(Option<i32>, Glyph)> {
+    let mut encoding = None;
+    let mut advance = bounding_box[0];
+    let mut bbx = bounding_box;
+    let mut bits = Vec::new();
+    while let Some(line) = lines.next() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("ENCODING") => encoding = Some(numbers(words, 1)?[0]).filter(|&n| n >= 0),
+            Some("DWIDTH") => advance = numbers(words, 1)?[0],
+            Some("BBX") => bbx.copy_from_slice(&numbers(words, 4)?),
+            Some("BITMAP") => {
+                let (width, height) = (bbx[0], bbx[1]);
+                if width < 0 || height < 0 || width > u16::MAX as i32 || height > u16::MAX as i32 {
+                    return Err(ErrorKind::FontError("BDF glyph has an invalid size".to_string()));
+                }
+                let width = width as usize;
+                bits = Vec::new();
+                for _ in 0..height {
+                    // Each row has a hex digit for every 4 pixels, so the bitmap can't
+                    // be bigger than the file
+                    let hex = lines.next().unwrap_or("").trim().as_bytes();
+                    if hex.len() * 4 < width {
+                        return Err(ErrorKind::FontError("BDF bitmap is cut off".to_string()));
+                    }
+                    for col in 0..width {
+                        let digit = (hex[col / 4] as char).to_digit(16).unwrap_or(0);
+                        bits.push(digit & (0x8 >> (col % 4)) != 0);
+                    }
+                }
+            },
+            Some("ENDCHAR") => {
+                let [width, height, x, y] = bbx;
+                let glyph = Glyph {
+                    width: width.clamp(0, u16::MAX as i32) as u16,
+                    height: height.clamp(0, u16::MAX as i32) as u16,
+                    // BDF offsets go up from the baseline to the bottom of the glyph
+                    offset: (x, y.saturating_add(height).saturating_neg()),
+                    advance: advance.clamp(0, u16::MAX as i32) as u16,
+                    bits,
+                };
+                return Ok((encoding, glyph));
+            },
+            _ => {},
+        }
+    }
+    Err(ErrorKind::FontError("BDF glyph has no ENDCHAR".to_string()))
+}
+
+impl<'a> Layer<'a> {
+    /// Draws text in pixels with a bitmap font, with the top left corner of the
+    /// first line at `p`. Each `'\n'` starts a new line. The text isn't transformed,
+    /// and the parts of it past the edge of the layer are cut off.
+    pub fn draw_text_px(&mut self, p: IPoint, text: &str, font: &Font, color: impl Into<Option<Color>>) -> Result<()> {
+        self.validate_i_points(&[p])?;
+        let color = color.into().unwrap_or(self.state.stroke_color);
+        let resolution = self.resolution();
+        let mut baseline = p.1 as i32 + font.ascent as i32;
+        for line in text.split('\n') {
+            let mut pen = p.0 as i32;
+            for glyph in line.chars().filter_map(|c| font.glyph(c)) {
+                for (x, y) in glyph.pixels() {
+                    let (x, y) = (pen + x, baseline + y);
+                    if x >= 0 && y >= 0 && (x as usize) < resolution.0 && (y as usize) < resolution.1 {
+                        self.plot((x as u16, y as u16), color)?;
+                    }
+                }
+                pen += glyph.advance as i32;
+            }
+            baseline += font.line_height() as i32;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BDF: &str = "STARTFONT 2.1
+FONTBOUNDINGBOX 4 3 0 -1
+FONT_ASCENT 2
+FONT_DESCENT 1
+CHARS 1
+STARTCHAR A
+ENCODING 65
+DWIDTH 5 0
+BBX 4 3 0 -1
+BITMAP
+F0
+90
+60
+ENDCHAR
+ENDFONT
+";
+
+    fn psf1(glyphs: &[[u8; 2]]) -> Vec<u8> {
+        let mut data = vec![0x36, 0x04, 0x00, 0x02];
+        for i in 0..256 {
+            data.extend_from_slice(glyphs.get(i).unwrap_or(&[0, 0]));
+        }
+        data
+    }
+
+    fn psf2(header_size: u32, count: u32, glyph_size: u32, height: u32, width: u32) -> Vec<u8> {
+        let mut data = vec![0x72, 0xb5, 0x4a, 0x86];
+        for &word in [0, header_size, 0, count, glyph_size, height, width].iter() {
+            data.extend_from_slice(&u32::to_le_bytes(word));
+        }
+        data
+    }
+
+    fn font_error<T>(result: Result<T>) -> bool {
+        matches!(result, Err(ErrorKind::FontError(_)))
+    }
+
+    #[test]
+    fn bdf_glyph_is_read() {
+        let font = Font::from_bdf(&mut BDF.as_bytes()).unwrap();
+        assert_eq!((font.ascent(), font.line_height()), (2, 3));
+        let glyph = font.glyph('A').unwrap();
+        assert_eq!((glyph.width, glyph.height, glyph.offset, glyph.advance), (4, 3, (0, -2), 5));
+        let pixels: Vec<_> = glyph.pixels().collect();
+        assert_eq!(pixels, vec![(0, -2), (1, -2), (2, -2), (3, -2), (0, -1), (3, -1), (1, 0), (2, 0)]);
+    }
+
+    #[test]
+    fn malformed_bdf_is_an_error() {
+        assert!(font_error(Font::from_bdf(&mut "STARTFONT 2.1\nENDFONT\n".as_bytes())));
+        assert!(font_error(Font::from_bdf(&mut BDF.replace("ENDCHAR", "").as_bytes())));
+        assert!(font_error(Font::from_bdf(&mut BDF.replace("ENCODING 65", "ENCODING x").as_bytes())));
+        assert!(font_error(Font::from_bdf(&mut BDF.replace("BBX 4 3", "BBX 2000000000 2000000000").as_bytes())));
+        assert!(font_error(Font::from_bdf(&mut BDF.replace("BBX 4 3", "BBX 12 3").as_bytes())));
+        let font = Font::from_bdf(&mut BDF.replace("FONT_ASCENT 2", "FONT_ASCENT 2147483647").as_bytes()).unwrap();
+        assert_eq!((font.ascent(), font.line_height()), (u16::MAX, u16::MAX));
+    }
+
+    #[test]
+    fn psf1_glyphs_are_code_points() {
+        let mut glyphs = [[0, 0]; 66];
+        glyphs[65] = [0x81, 0xFF];
+        let font = Font::from_psf(&mut &psf1(&glyphs)[..]).unwrap();
+        assert_eq!(font.line_height(), 2);
+        let pixels: Vec<_> = font.glyph('A').unwrap().pixels().collect();
+        assert_eq!(pixels.len(), 10);
+        assert_eq!(&pixels[..2], &[(0, -2), (7, -2)]);
+    }
+
+    #[test]
+    fn psf2_with_unicode_table() {
+        let mut data = psf2(32, 1, 1, 1, 8);
+        data[12] = 1;
+        data.push(0xAA);
+        data.extend_from_slice("é".as_bytes());
+        data.push(0xFF);
+        let font = Font::from_psf(&mut &data[..]).unwrap();
+        assert!(font.has_glyph('é'));
+        assert!(!font.has_glyph('\0'));
+    }
+
+    #[test]
+    fn malformed_psf_is_an_error() {
+        assert!(font_error(Font::from_psf(&mut &b"not a font"[..])));
+        assert!(font_error(Font::from_psf(&mut &psf1(&[])[..100])));
+        assert!(font_error(Font::from_psf(&mut &psf2(32, 1, 1, 1, 8)[..20])));
+        // No glyph data
+        assert!(font_error(Font::from_psf(&mut &psf2(32, 1, 1, 1, 8)[..])));
+        // Zero-sized glyphs
+        assert!(font_error(Font::from_psf(&mut &psf2(32, 1, 0, 0, 0)[..])));
+        assert!(font_error(Font::from_psf(&mut &psf2(32, u32::MAX, 0, 1, 8)[..])));
+        // Sizes that would overflow or need huge allocations
+        assert!(font_error(Font::from_psf(&mut &psf2(32, u32::MAX, u32::MAX, 1, 8)[..])));
+        assert!(font_error(Font::from_psf(&mut &psf2(u32::MAX, 1, 1, 1, 8)[..])));
+        assert!(font_error(Font::from_psf(&mut &psf2(32, 1, 1, u32::MAX, u32::MAX)[..])));
+        assert!(font_error(Font::from_psf(&mut &psf2(32, 1, 1, 2, 8)[..])));
+    }
+}
@@ -0,0 +1,486 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! Decoders for the image formats that the `bmp` crate can't read. Other than
+//! BMP, each of them is only included with the cargo feature of the same name.
+
+#[cfg(any(feature = "png", feature = "gif"))]
+use std::time::Duration;
+#[cfg(any(feature = "png", feature = "gif"))]
+use crate::animation::Frame;
+use crate::{bitmap::RgbaImage, error::ErrorKind, Result};
+
+/// Turns an error from one of the decoders into an `ErrorKind`
+fn image_error(err: impl std::fmt::Display) -> ErrorKind {
+    ErrorKind::ImageError(err.to_string())
+}
+
+/// Decodes a BMP image. The `bmp` crate can't read 32-bit images, which are the
+/// ones that can have alpha, so those are decoded here and the rest are left to it.
+pub(crate) fn bmp(data: &[u8]) -> Result<RgbaImage> {
+    let u16_at = |i: usize| data.get(i..i + 2).map(|b| u16::from_le_bytes([b[0], b[1]]));
+    let u32_at = |i: usize| data.get(i..i + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]));
+    // Headers older than BITMAPINFOHEADER are shorter, so they have something else at 28
+    if !matches!(u32_at(14), Some(size) if size >= 40) || u16_at(28) != Some(32) {
+        return Ok(RgbaImage::from(&bmp::from_reader(&mut &data[..])?));
+    }
+
+    let field = |i: usize| u32_at(i).ok_or_else(|| image_error("BMP header is too short"));
+    let offset = field(10)? as usize;
+    let header_size = field(14)?;
+    let width = field(18)? as i32;
+    let height = field(22)? as i32;
+    let compression = field(30)?;
+    // The masks of the bits of each pixel that are red, green, blue and alpha. Without
+    // bitfields, the pixels are blue, green, red and a byte that many programs use for
+    // alpha, even though it is meant to be left unused.
+    let masks = match compression {
+        0 => [0x00FF_0000, 0x0000_FF00, 0x0000_00FF, 0xFF00_0000],
+        3 | 6 => {
+            let alpha = if header_size >= 56 || compression == 6 { field(66)? } else { 0 };
+            [field(54)?, field(58)?, field(62)?, alpha]
+        },
+        _ => return Err(image_error(format!("unsupported 32-bit BMP compression {}", compression))),
+    };
+    if width <= 0 || height == 0 {
+        return Err(image_error(format!("invalid BMP size {}x{}", width, height)));
+    }
+    // Rows go from the bottom up, unless the height is negative
+    let (width, height, top_down) = (width as u32, height.unsigned_abs(), height < 0);
+    if (data.len() as u64) < offset as u64 + width as u64 * height as u64 * 4 {
+        return Err(image_error("BMP pixel data is too short"));
+    }
+
+    let channel = |px: u32, mask: u32| {
+        if mask == 0 {
+            return 0;
+        }
+        let max = (mask >> mask.trailing_zeros()) as u64;
+        (((px & mask) >> mask.trailing_zeros()) as u64 * 255 / max) as u8
+    };
+    let mut img = RgbaImage::new(width, height);
+    let mut has_alpha = false;
+    for y in 0..height {
+        let row = if top_down { y } else { height - 1 - y };
+        for x in 0..width {
+            let i = offset + (row as usize * width as usize + x as usize) * 4;
+            let px = u32::from_le_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]);
+            let alpha = if masks[3] == 0 { 255 } else { channel(px, masks[3]) };
+            has_alpha |= alpha != 0;
+            img.set_pixel(x, y, [channel(px, masks[0]), channel(px, masks[1]), channel(px, masks[2]), alpha]);
+        }
+    }
+    // Programs that don't use alpha often leave it as 0, which would make the whole
+    // image invisible, so images where every pixel is transparent are made opaque
+    if !has_alpha {
+        for y in 0..height {
+            for x in 0..width {
+                let [r, g, b, _] = img.get_pixel(x, y);
+                img.set_pixel(x, y, [r, g, b, 255]);
+            }
+        }
+    }
+    Ok(img)
+}
+
+/// Decodes a PNG image. Palettes, grayscale and transparency are all expanded
+/// to red, green, blue and alpha. For APNG animations, the default image is decoded.
+#[cfg(feature = "png")]
+pub(crate) fn png(data: &[u8]) -> Result<RgbaImage> {
+    let (info, mut reader) = png_reader(data)?;
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf).map_err(image_error)?;
+    png_pixels(&buf, info.width, info.height, reader.output_color_type())
+}
+
+/// Decodes every frame of an APNG animation, and how many times it plays (or `None`
+/// to play forever). PNGs that aren't animated have one frame.
+#[cfg(feature = "png")]
+pub(crate) fn apng(data: &[u8]) -> Result<(Vec<Frame>, Option<u32>)> {
+    use png::{BlendOp, DisposeOp};
+
+    let (info, mut reader) = png_reader(data)?;
+    let animation = match reader.info().animation_control {
+        Some(animation) => animation,
+        None => {
+            let mut buf = vec![0; info.buffer_size()];
+            reader.next_frame(&mut buf).map_err(image_error)?;
+            let image = png_pixels(&buf, info.width, info.height, reader.output_color_type())?;
+            return Ok((vec![Frame::new(image, Duration::from_secs(0))], Some(1)));
+        },
+    };
+    // If the first frame control comes after the image data, the default image
+    // is only shown by programs that can't play animations
+    let default_is_frame = reader.info().frame_control.is_some();
+    // The frame count comes from the file, so the frames aren't allocated up front. A
+    // file with fewer frames than it says fails when it runs out.
+    let count = animation.num_frames.saturating_add(if default_is_frame { 0 } else { 1 });
+    let mut buf = vec![0; info.buffer_size()];
+    let mut canvas = RgbaImage::new(info.width, info.height);
+    let mut frames = Vec::new();
+    for i in 0..count {
+        reader.next_frame(&mut buf).map_err(image_error)?;
+        if i == 0 && !default_is_frame {
+            continue;
+        }
+        let control = reader.info().frame_control.unwrap_or_default();
+        let image = png_pixels(&buf, control.width, control.height, reader.output_color_type())?;
+        let rect = (control.x_offset, control.y_offset, control.width, control.height);
+        // Only frames that are disposed of by going back to the frame before them need
+        // a copy of it, and the first frame has nothing before it
+        let previous = match control.dispose_op {
+            DisposeOp::Previous if !frames.is_empty() => Some(canvas.clone()),
+            _ => None,
+        };
+        draw_frame(&mut canvas, rect, &image, control.blend_op == BlendOp::Over);
+        // A denominator of 0 means hundredths of a second
+        let den = if control.delay_den == 0 { 100 } else { control.delay_den as u64 };
+        let delay = Duration::from_nanos(control.delay_num as u64 * 1_000_000_000 / den);
+        frames.push(Frame::new(canvas.clone(), frame_delay(delay)));
+        match (control.dispose_op, previous) {
+            (DisposeOp::None, _) => {},
+            // The first frame is cleared when it would go back, since there's nothing to go back to
+            (DisposeOp::Background, _) | (DisposeOp::Previous, None) => clear_frame(&mut canvas, rect),
+            (DisposeOp::Previous, Some(previous)) => canvas = previous,
+        }
+    }
+    let plays = if animation.num_plays == 0 { None } else { Some(animation.num_plays) };
+    Ok((frames, plays))
+}
+
+/// Encodes an image as a PNG
+#[cfg(feature = "png")]
+pub(crate) fn write_png(img: &RgbaImage, dst: &mut impl std::io::Write) -> Result<()> {
+    use png::{BitDepth, ColorType, Encoder};
+
+    let mut encoder = Encoder::new(dst, img.width(), img.height());
+    encoder.set_color(ColorType::RGBA);
+    encoder.set_depth(BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(image_error)?;
+    let mut data = Vec::with_capacity(img.width() as usize * img.height() as usize * 4);
+    for y in 0..img.height() {
+        for x in 0..img.width() {
+            data.extend_from_slice(&img.get_pixel(x, y));
+        }
+    }
+    writer.write_image_data(&data).map_err(image_error)
+}
+
+/// Starts decoding a PNG image, with everything converted to 8 bits per channel
+#[cfg(feature = "png")]
+fn png_reader(data: &[u8]) -> Result<(png::OutputInfo, png::Reader<&[u8]>)> {
+    use png::{Decoder, Transformations};
+
+    let mut decoder = Decoder::new(data);
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+    decoder.read_info().map_err(image_error)
+}
+
+/// Converts a decoded frame into an image
+#[cfg(feature = "png")]
+fn png_pixels(buf: &[u8], width: u32, height: u32, (color_type, bit_depth): (png::ColorType, png::BitDepth)) -> Result<RgbaImage> {
+    use png::{BitDepth, ColorType};
+
+    if bit_depth != BitDepth::Eight {
+        return Err(image_error(format!("unsupported PNG bit depth {:?}", bit_depth)));
+    }
+    let channels = match color_type {
+        ColorType::Grayscale => 1,
+        ColorType::GrayscaleAlpha => 2,
+        ColorType::RGB => 3,
+        ColorType::RGBA => 4,
+        ColorType::Indexed => return Err(image_error("PNG palette wasn't expanded")),
+    };
+    let mut img = RgbaImage::new(width, height);
+    for (i, px) in buf.chunks_exact(channels).take(width as usize * height as usize).enumerate() {
+        let rgba = match channels {
+            1 => [px[0], px[0], px[0], 255],
+            2 => [px[0], px[0], px[0], px[1]],
+            3 => [px[0], px[1], px[2], 255],
+            _ => [px[0], px[1], px[2], px[3]],
+        };
+        img.set_pixel(i as u32 % width, i as u32 / width, rgba);
+    }
+    Ok(img)
+}
+
+/// Decodes a JPEG image
+#[cfg(feature = "jpeg")]
+pub(crate) fn jpeg(data: &[u8]) -> Result<RgbaImage> {
+    use jpeg_decoder::{Decoder, PixelFormat};
+
+    let mut decoder = Decoder::new(data);
+    let pixels = decoder.decode().map_err(image_error)?;
+    let info = decoder.info().ok_or_else(|| image_error("JPEG has no image"))?;
+    let mut img = RgbaImage::new(info.width as u32, info.height as u32);
+    let channels = match info.pixel_format {
+        PixelFormat::L8 => 1,
+        PixelFormat::RGB24 => 3,
+        PixelFormat::CMYK32 => 4,
+    };
+    for (i, px) in pixels.chunks_exact(channels).enumerate() {
+        let rgba = match channels {
+            1 => [px[0], px[0], px[0], 255],
+            3 => [px[0], px[1], px[2], 255],
+            // The decoder inverts CMYK, so the values are already how much light there is
+            _ => {
+                let k = px[3] as u16;
+                [(px[0] as u16 * k / 255) as u8, (px[1] as u16 * k / 255) as u8, (px[2] as u16 * k / 255) as u8, 255]
+            },
+        };
+        img.set_pixel(i as u32 % info.width as u32, i as u32 / info.width as u32, rgba);
+    }
+    Ok(img)
+}
+
+/// Decodes the first frame of a GIF image
+#[cfg(feature = "gif")]
+pub(crate) fn gif(data: &[u8]) -> Result<RgbaImage> {
+    let mut decoder = gif_decoder(data)?;
+    let (width, height) = (decoder.width() as u32, decoder.height() as u32);
+    let frame = decoder.read_next_frame().map_err(image_error)?.ok_or_else(|| image_error("GIF has no frames"))?;
+    // The frame can be smaller than the image, and the rest of the image is transparent
+    let mut img = RgbaImage::new(width, height);
+    draw_frame(&mut img, gif_rect(frame), &gif_pixels(frame), false);
+    Ok(img)
+}
+
+/// Decodes every frame of a GIF animation, and how many times it plays (or `None`
+/// to play forever)
+#[cfg(feature = "gif")]
+pub(crate) fn gif_animation(data: &[u8]) -> Result<(Vec<Frame>, Option<u32>)> {
+    use gif::DisposalMethod;
+
+    let mut decoder = gif_decoder(data)?;
+    let mut canvas = RgbaImage::new(decoder.width() as u32, decoder.height() as u32);
+    let mut frames = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(image_error)? {
+        let rect = gif_rect(frame);
+        let previous = match frame.dispose {
+            DisposalMethod::Previous if !frames.is_empty() => Some(canvas.clone()),
+            _ => None,
+        };
+        // GIF pixels are either opaque or transparent, so drawing them over what's
+        // already there just skips the transparent ones
+        draw_frame(&mut canvas, rect, &gif_pixels(frame), true);
+        // Delays are in hundredths of a second
+        frames.push(Frame::new(canvas.clone(), frame_delay(Duration::from_millis(frame.delay as u64 * 10))));
+        match (frame.dispose, previous) {
+            (DisposalMethod::Any, _) | (DisposalMethod::Keep, _) => {},
+            (DisposalMethod::Background, _) | (DisposalMethod::Previous, None) => clear_frame(&mut canvas, rect),
+            (DisposalMethod::Previous, Some(previous)) => canvas = previous,
+        }
+    }
+    if frames.is_empty() {
+        return Err(image_error("GIF has no frames"));
+    }
+    Ok((frames, gif_plays(data)))
+}
+
+#[cfg(feature = "gif")]
+fn gif_decoder(data: &[u8]) -> Result<gif::Decoder<&[u8]>> {
+    use gif::{ColorOutput, DecodeOptions};
+
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::RGBA);
+    options.read_info(data).map_err(image_error)
+}
+
+#[cfg(feature = "gif")]
+fn gif_rect(frame: &gif::Frame<'_>) -> (u32, u32, u32, u32) {
+    (frame.left as u32, frame.top as u32, frame.width as u32, frame.height as u32)
+}
+
+#[cfg(feature = "gif")]
+fn gif_pixels(frame: &gif::Frame<'_>) -> RgbaImage {
+    let mut img = RgbaImage::new(frame.width as u32, frame.height as u32);
+    for (i, px) in frame.buffer.chunks_exact(4).enumerate() {
+        img.set_pixel(i as u32 % frame.width as u32, i as u32 / frame.width as u32, [px[0], px[1], px[2], px[3]]);
+    }
+    img
+}
+
+/// Finds how many times a GIF plays. The `gif` crate doesn't read this, so the
+/// NETSCAPE2.0 extension is looked for here. Without it, the GIF plays once.
+#[cfg(feature = "gif")]
+fn gif_plays(data: &[u8]) -> Option<u32> {
+    const EXTENSION: &[u8] = b"NETSCAPE2.0\x03\x01";
+    let i = match data.windows(EXTENSION.len()).position(|w| w == EXTENSION) {
+        Some(i) => i + EXTENSION.len(),
+        None => return Some(1),
+    };
+    match data.get(i..i + 2) {
+        // This is how many times it repeats, so the first time isn't counted
+        Some(&[0, 0]) => None,
+        Some(&[low, high]) => Some(u16::from_le_bytes([low, high]) as u32 + 1),
+        _ => Some(1),
+    }
+}
+
+/// Gets how long an animation frame is shown for. Browsers show frames without a
+/// delay for a tenth of a second, and so many animations rely on that.
+#[cfg(any(feature = "png", feature = "gif"))]
+fn frame_delay(delay: Duration) -> Duration {
+    if delay == Duration::from_secs(0) { Duration::from_millis(100) } else { delay }
+}
+
+/// Draws one frame of an animation onto the image the frames are built up in. If
+/// `over` is true, the frame is drawn over what's already there, and otherwise it
+/// replaces it.
+#[cfg(any(feature = "png", feature = "gif"))]
+fn draw_frame(canvas: &mut RgbaImage, (left, top, width, height): (u32, u32, u32, u32), frame: &RgbaImage, over: bool) {
+    for y in 0..height.min(canvas.height().saturating_sub(top)) {
+        for x in 0..width.min(canvas.width().saturating_sub(left)) {
+            let src = frame.get_pixel(x, y);
+            let (x, y) = (left + x, top + y);
+            let px = if over && src[3] < 255 {
+                let dst = canvas.get_pixel(x, y);
+                let (src_a, dst_a) = (src[3] as u32, dst[3] as u32 * (255 - src[3] as u32) / 255);
+                let a = src_a + dst_a;
+                if a == 0 {
+                    continue;
+                }
+                let channel = |i: usize| ((src[i] as u32 * src_a + dst[i] as u32 * dst_a) / a) as u8;
+                [channel(0), channel(1), channel(2), a as u8]
+            }
+            else {
+                src
+            };
+            canvas.set_pixel(x, y, px);
+        }
+    }
+}
+
+/// Makes the part of the image a frame covered transparent
+#[cfg(any(feature = "png", feature = "gif"))]
+fn clear_frame(canvas: &mut RgbaImage, (left, top, width, height): (u32, u32, u32, u32)) {
+    for y in top..top.saturating_add(height).min(canvas.height()) {
+        for x in left..left.saturating_add(width).min(canvas.width()) {
+            canvas.set_pixel(x, y, [0; 4]);
+        }
+    }
+}
+
+/// Decodes a WebP image. For animations, only the first frame is decoded.
+#[cfg(feature = "webp")]
+pub(crate) fn webp(data: &[u8]) -> Result<RgbaImage> {
+    use std::io::Cursor;
+    use image_webp::WebPDecoder;
+
+    let mut decoder = WebPDecoder::new(Cursor::new(data)).map_err(image_error)?;
+    let (width, height) = decoder.dimensions();
+    let size = decoder.output_buffer_size().ok_or_else(|| image_error("WebP image is too big"))?;
+    let mut buf = vec![0; size];
+    decoder.read_image(&mut buf).map_err(image_error)?;
+    let channels = if decoder.has_alpha() { 4 } else { 3 };
+    let mut img = RgbaImage::new(width, height);
+    for (i, px) in buf.chunks_exact(channels).enumerate() {
+        let alpha = if channels == 4 { px[3] } else { 255 };
+        img.set_pixel(i as u32 % width, i as u32 / width, [px[0], px[1], px[2], alpha]);
+    }
+    Ok(img)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes a 32-bit BMP. With `masks`, it has a BITMAPV3INFOHEADER with bitfields.
+    fn bmp32(width: i32, height: i32, masks: Option<[u32; 4]>, pixels: &[u32]) -> Vec<u8> {
+        let header_size: u32 = if masks.is_some() { 56 } else { 40 };
+        let offset = 14 + header_size;
+        let mut data = b"BM".to_vec();
+        for &word in [offset + pixels.len() as u32 * 4, 0, offset, header_size].iter() {
+            data.extend_from_slice(&word.to_le_bytes());
+        }
+        data.extend_from_slice(&width.to_le_bytes());
+        data.extend_from_slice(&height.to_le_bytes());
+        data.extend_from_slice(&[1, 0, 32, 0]);
+        data.extend_from_slice(&(if masks.is_some() { 3u32 } else { 0 }).to_le_bytes());
+        data.extend_from_slice(&[0; 20]);
+        for &mask in masks.iter().flatten() {
+            data.extend_from_slice(&mask.to_le_bytes());
+        }
+        for &px in pixels {
+            data.extend_from_slice(&px.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn bmp32_rows_go_up() {
+        let img = bmp(&bmp32(2, 2, None, &[0x80FF_0000, 0x8000_FF00, 0xFF00_00FF, 0x0000_0000])).unwrap();
+        assert_eq!((img.width(), img.height()), (2, 2));
+        assert_eq!(img.get_pixel(0, 0), [0, 0, 255, 255]);
+        assert_eq!(img.get_pixel(1, 0), [0, 0, 0, 0]);
+        assert_eq!(img.get_pixel(0, 1), [255, 0, 0, 128]);
+        assert_eq!(img.get_pixel(1, 1), [0, 255, 0, 128]);
+    }
+
+    #[test]
+    fn bmp32_without_alpha_is_opaque() {
+        let img = bmp(&bmp32(1, -2, None, &[0x0012_3456, 0x0000_0000])).unwrap();
+        assert_eq!(img.get_pixel(0, 0), [0x12, 0x34, 0x56, 255]);
+        assert_eq!(img.get_pixel(0, 1), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn bmp32_bitfields() {
+        let masks = [0x0000_00FF, 0x0000_FF00, 0x00FF_0000, 0xFF00_0000];
+        let img = bmp(&bmp32(1, 1, Some(masks), &[0x4030_2010])).unwrap();
+        assert_eq!(img.get_pixel(0, 0), [0x10, 0x20, 0x30, 0x40]);
+    }
+
+    #[test]
+    fn malformed_bmp32_is_an_error() {
+        let is_error = |data: &[u8]| matches!(bmp(data), Err(ErrorKind::ImageError(_)));
+        let mut cut_off = bmp32(2, 2, None, &[0; 4]);
+        cut_off.truncate(cut_off.len() - 1);
+        assert!(is_error(&cut_off));
+        assert!(is_error(&bmp32(0, 2, None, &[])));
+        assert!(is_error(&bmp32(1, 0, None, &[])));
+        assert!(is_error(&bmp32(i32::MAX, i32::MIN, None, &[0])));
+        let mut compressed = bmp32(1, 1, None, &[0]);
+        compressed[30] = 1;
+        assert!(is_error(&compressed));
+        assert!(is_error(&bmp32(1, 1, None, &[0])[..40]));
+    }
+
+    #[cfg(feature = "gif")]
+    #[test]
+    fn gif_plays_from_netscape_extension() {
+        let gif = |repeats: &[u8]| [&b"GIF89a...!\xFF\x0BNETSCAPE2.0\x03\x01"[..], repeats, b"\0;"].concat();
+        assert_eq!(gif_plays(&gif(&[0, 0])), None);
+        assert_eq!(gif_plays(&gif(&[2, 0])), Some(3));
+        assert_eq!(gif_plays(&gif(&[0, 1])), Some(257));
+        assert_eq!(gif_plays(b"GIF89a;"), Some(1));
+        // Cut off in the middle of the count
+        assert_eq!(gif_plays(&b"GIF89a!\xFF\x0BNETSCAPE2.0\x03\x01\x05"[..]), Some(1));
+    }
+
+    #[cfg(any(feature = "png", feature = "gif"))]
+    #[test]
+    fn frames_without_delay_get_one() {
+        assert_eq!(frame_delay(Duration::from_secs(0)), Duration::from_millis(100));
+        assert_eq!(frame_delay(Duration::from_millis(20)), Duration::from_millis(20));
+    }
+
+    #[cfg(any(feature = "png", feature = "gif"))]
+    #[test]
+    fn frames_are_drawn_in_their_rectangle() {
+        let mut canvas = RgbaImage::new(3, 3);
+        let mut frame = RgbaImage::new(2, 2);
+        frame.set_pixel(0, 0, [255, 0, 0, 255]);
+        frame.set_pixel(1, 1, [0, 0, 255, 128]);
+        // The frame goes past the edge of the canvas, which is cut off
+        draw_frame(&mut canvas, (2, 1, 2, 2), &frame, true);
+        assert_eq!(canvas.get_pixel(2, 1), [255, 0, 0, 255]);
+        // Half of the blue pixel is drawn over the red one
+        draw_frame(&mut canvas, (1, 0, 2, 2), &frame, true);
+        assert_eq!(canvas.get_pixel(2, 1), [127, 0, 128, 255]);
+        draw_frame(&mut canvas, (1, 0, 2, 2), &frame, false);
+        assert_eq!(canvas.get_pixel(2, 1), [0, 0, 255, 128]);
+        clear_frame(&mut canvas, (1, 0, 1, 1));
+        assert_eq!((canvas.get_pixel(1, 0), canvas.get_pixel(2, 1)), ([0; 4], [0, 0, 255, 128]));
+    }
+}
@@ -11,36 +11,73 @@
 #![warn(missing_docs)]
 
 use std::{
-    io::{stdout, Read, Write, StdoutLock}, 
+    io::{self, stdout, Read, Write},
     iter::Iterator,
     sync::{
-        atomic::{AtomicUsize, AtomicBool, Ordering},
-        mpsc::{channel, Sender, TryRecvError},
-        Once, Arc, Mutex, RwLock, Condvar
+        atomic::{AtomicUsize, Ordering},
+        mpsc::channel,
+        Once, Arc, Mutex, MutexGuard, PoisonError
     },
-    thread::{self, JoinHandle},
+    thread::JoinHandle,
     marker::PhantomData,
+    ops::Deref,
     collections::HashSet,
-    time::{Instant, Duration},
+    time::Instant,
 };
 use crossterm::{
     tty::IsTty,
-    style::{ContentStyle, StyledContent, PrintStyledContent, style},
+    style::{ContentStyle, StyledContent},
     terminal::{self, EnterAlternateScreen, LeaveAlternateScreen, enable_raw_mode, disable_raw_mode}, 
-    cursor, event, execute, queue, ExecutableCommand, QueueableCommand
+    cursor, event, execute, QueueableCommand
 };
 //use num_traits::{Zero, One};
-use bmp;
 use lazy_static::lazy_static;
 #[cfg(unix)]
 use libc::{winsize, ioctl, STDOUT_FILENO, TIOCGWINSZ};
 
-pub use crossterm::style::Color;
+pub use crossterm::style::{Attribute, Attributes, Color};
 pub use event::{Event, KeyEvent, KeyCode, KeyModifiers, MouseEvent, MouseEventKind, MouseButton};
 
 pub mod error;
 use error::*;
 
+pub mod state;
+use state::*;
+
+mod cell;
+mod boxes;
+mod color;
+mod raster;
+mod antialias;
+mod shapes;
+mod stroke;
+mod flood;
+
+pub mod path;
+
+pub mod paint;
+use paint::*;
+
+pub mod bitmap;
+use bitmap::*;
+
+mod formats;
+
+pub mod animation;
+mod scheduler;
+use scheduler::FrameScheduler;
+
+pub mod sprite;
+
+pub mod border;
+
+pub mod text;
+
+pub mod font;
+
+#[cfg(feature = "truetype")]
+pub mod truetype;
+
 const DEFAULT_PX_SIZE: (f32, f32) = (1.0, 0.5);
 
 #[cfg(windows)]
@@ -67,17 +104,22 @@ lazy_static! {
     };
 }
 
-//static CANVAS_COUNT: AtomicUsize = AtomicUsize::new(0);
+static CANVAS_COUNT: AtomicUsize = AtomicUsize::new(0);
 // IDK if this works
 /*lazy_static! {
     static ref CANVAS_LOCK: (AtomicBool, Condvar) = (AtomicBool::new(false), Condvar::new());
 }*/
 
+/// What a canvas does when the terminal is resized
 pub enum ResizeType {
-    Manual(Box<dyn FnMut() -> ()>),
+    /// Calls a function, which can redraw everything
+    Manual(Box<dyn FnMut()>),
+
+    /// Keeps what was drawn where it was on each axis
     Auto(ResizeAxis, ResizeAxis) // TODO: Add way to add cutoff function (hide edges, stop until resized)
 }
 
+/// Where what was drawn stays along one axis when the terminal is resized
 #[derive(Copy, Clone, Debug)]
 pub enum ResizeAxis {
     /// Either the top (vertical) or left (horizontal)
@@ -147,9 +189,17 @@ pub struct Canvas {
 
     layer_count: Arc<AtomicUsize>,
 
+    /// The buffer of every layer, from the bottom up, so that the canvas can read
+    /// them without going through the listener thread
+    layers: Arc<Mutex<Vec<Buffer>>>,
+
     // TODO: Make accessible from multiple threads
     // TODO: Replace with more efficient data structure, like HashSet ~~or BTreeSet~~
     changed: Arc<Mutex<HashSet<usize>>>,
+
+    /// Calls the callbacks from `request_animation_frame`, on `frame_thread`
+    scheduler: Arc<FrameScheduler>,
+    frame_thread: Option<JoinHandle<()>>,
     // RwLock may not be the perfect solution (and how it works depends on the kernel),
     //      see https://www.reddit.com/r/rust/comments/f4zldz/i_audited_3_different_implementation_of_async/?utm_source=share&utm_medium=web2x&context=3
     //      but it is approximately what I want (behavior-wise, not intent-wise), so it's good
@@ -177,10 +227,13 @@ impl Canvas {
         let mut out = stdout();
         //let out = out.lock();
         //let stdout_lock_ptr = &stdout_lock as *const StdoutLock<'_>;
-        //if CANVAS_COUNT.fetch_add(1, Ordering::AcqRel) == 0 { 
-            execute!(out, EnterAlternateScreen, cursor::Hide)?;
-            enable_raw_mode()?;
-        //}
+        if CANVAS_COUNT.fetch_add(1, Ordering::AcqRel) == 0 {
+            // The canvas isn't made, so it won't be dropped to take this back
+            if let Err(err) = execute!(out, EnterAlternateScreen, cursor::Hide).and_then(|_| enable_raw_mode()) {
+                CANVAS_COUNT.fetch_sub(1, Ordering::AcqRel);
+                return Err(err.into());
+            }
+        }
         static ONCE: Once = Once::new();
         ONCE.call_once(|| {
             if !out.is_tty() {
@@ -188,8 +241,8 @@ impl Canvas {
             }
         });
         let changed = Arc::new(Mutex::new(HashSet::new()));
-        let update_lock = Arc::new(RwLock::new(()));
         let layer_count = Arc::new(AtomicUsize::new(0));
+        let scheduler = Arc::new(FrameScheduler::new());
         //let (sender, receiver) = channel();
         //let out_wrap = Arc::new(Mutex::new(out));
         Ok(Self {
@@ -197,6 +250,9 @@ impl Canvas {
             resize_type: ResizeType::Auto(ResizeAxis::Start, ResizeAxis::Start),
             changed: Arc::clone(&changed),
             layer_count: Arc::clone(&layer_count),
+            layers: Arc::new(Mutex::new(Vec::new())),
+            frame_thread: Some(scheduler.spawn()),
+            scheduler,
             /*sender,
             update_lock: Arc::clone(&update_lock),
             // TODO: Add resize listener
@@ -262,20 +318,21 @@ impl Canvas {
                     let cols = terminal::size().expect("Unable to get terminal size").0;
                     //let mut out = stdout();
                     let mut update_px = |i: usize| {
-                        let mut c = StyledContent::new(ContentStyle::new(), ' ');
-                        for layer in layer_refs.iter() {
-                            underlay(&mut c, &layer[i]);
-                            if !underlay_possible(&c) {
-                                break;
-                            }
+                        if let Some(c) = present(&layer_refs, i, cols) {
+                            queue!(out,
+                                cursor::MoveTo((i % cols as usize) as u16, (i / cols as usize) as u16),
+                                PrintStyledContent(c)
+                            ).unwrap();
                         }
-                        queue!(out,
-                            cursor::MoveTo((i % cols as usize) as u16, (i / cols as usize) as u16),
-                            PrintStyledContent(c)
-                        ).unwrap();
                     };
                     if let DrawStarted = msg {
-                        for &i in changed.lock().unwrap().iter() {
+                        // A cell that changed can cover half of a wide character in the
+                        // cells next to it, so they are drawn again too
+                        let len = layer_refs[0].len();
+                        let to_update: HashSet<usize> = changed.lock().unwrap().iter()
+                            .flat_map(|&i| i.saturating_sub(1)..(i + 2).min(len))
+                            .collect();
+                        for i in to_update {
                             update_px(i);
                         }
                     }
@@ -291,22 +348,30 @@ impl Canvas {
         })
     }
 
+    /// Creates a layer on top of all of the others
     pub fn new_layer<'a>(&'a self) -> Layer<'a> {
         self.new_layer_at(self.layer_count.load(Ordering::Acquire))
     }
 
+    /// Creates a layer at index `i`, where 0 is the bottom
+    ///
+    /// # Panics
+    ///
+    /// Panics if `i` is more than the number of layers
     pub fn new_layer_at<'a>(&'a self, i: usize) -> Layer<'a> {
-        let _lock = self.update_lock.read();
         let buf = Arc::new(Mutex::new(vec![StyledContent::new(ContentStyle::new(), ' '); Layer::size()]));
         let len = self.layer_count.fetch_add(1, Ordering::Acquire);
         if i > len {
             panic!("Index {} is out of bounds for length {}", i, len);
         }
-        let _ = self.sender.send(Message::NewLayer(Arc::clone(&buf), i));
+        self.layers.lock().unwrap_or_else(PoisonError::into_inner).insert(i, Arc::clone(&buf));
         // TODO: Add method to add layers in different places
         Layer {
             buf,
             changed: Arc::clone(&self.changed),
+            layers: Arc::clone(&self.layers),
+            state: DrawState::default(),
+            saved_states: Vec::new(),
             phantom: PhantomData
         }
     }
@@ -314,11 +379,82 @@ impl Canvas {
     /// Gets the resolution of a canvas
     pub fn resolution() -> (u16, u16) {
         let (cols, rows) = terminal::size().expect("Unable to get terminal size");
-        ((cols as f32 / PX_SIZE.0) as u16, (rows as f32 * PX_SIZE.1) as u16)
+        ((cols as f32 / PX_SIZE.0) as u16, (rows as f32 / PX_SIZE.1) as u16)
     }
 
-    pub fn request_animation_frame(&self, f: impl FnOnce(Instant) + Send) -> i64 {
-        todo!()
+    /// Gets an image of everything drawn on the canvas, with the layers put together
+    /// the same way as they are on the screen. Each cell is turned back into the
+    /// pixels it was made from, so a half block gives two pixels and a quadrant
+    /// gives four. Text can't be turned back into pixels, so cells with text in
+    /// them are filled with the color behind the text.
+    pub fn screenshot(&self) -> Result<RgbaImage> {
+        let layers = self.layers.lock()?;
+        let mut bufs = Vec::with_capacity(layers.len());
+        for layer in layers.iter().rev() {
+            bufs.push(layer.lock()?);
+        }
+        let cols = terminal::size()?.0;
+        let (width, height) = Canvas::resolution();
+        let mut img = RgbaImage::new(width as u32, height as u32);
+        let len = bufs.iter().map(|buf| buf.len()).min().unwrap_or(0);
+        for y in 0..height {
+            for x in 0..width {
+                let (i, sub) = cell::locate((x, y), cols);
+                if i >= len {
+                    continue;
+                }
+                // The second half of a wide character has the same color behind it
+                // as the first half
+                let c = present(&bufs, i, cols).or_else(|| present(&bufs, i - 1, cols));
+                if let Some((r, g, b)) = c.and_then(|c| cell::decode(&c)[sub]).and_then(color::to_rgb) {
+                    img.set_pixel(x as u32, y as u32, [r, g, b, 255]);
+                }
+            }
+        }
+        Ok(img)
+    }
+
+    /// Calls `f` once, at the start of the next frame, with the time the frame
+    /// started. Frames are about a 60th of a second apart, and every callback in a
+    /// frame gets the same time. To keep animating, `f` has to request another frame.
+    /// Returns an id that can be given to `cancel_animation_frame`.
+    ///
+    /// Callbacks are called on a thread of the canvas's own, so layers can't be
+    /// used in them. Use `wait_for_frame` to draw on each frame instead.
+    pub fn request_animation_frame(&self, f: impl FnOnce(Instant) + Send + 'static) -> i64 {
+        self.scheduler.request(Box::new(f))
+    }
+
+    /// Stops a callback from `request_animation_frame` from being called, if it
+    /// hasn't been already
+    pub fn cancel_animation_frame(&self, id: i64) {
+        self.scheduler.cancel(id);
+    }
+
+    /// Waits for the start of the next frame, and returns the time it started,
+    /// which is the same time given to the `request_animation_frame` callbacks.
+    ///
+    /// ```no_run
+    /// # use graphic_cli::{Canvas, animation::Animation};
+    /// # fn main() -> graphic_cli::error::Result<()> {
+    /// # let canvas = Canvas::new()?;
+    /// # let mut layer = canvas.new_layer();
+    /// let mut spinner = Animation::from_reader(&mut std::fs::File::open("spinner.gif")?)?;
+    /// while !spinner.is_finished() {
+    ///     if spinner.tick(canvas.wait_for_frame()) {
+    ///         layer.draw_animation(0, 0, &spinner)?;
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn wait_for_frame(&self) -> Instant {
+        let (sender, receiver) = channel();
+        self.request_animation_frame(move |now| {
+            let _ = sender.send(now);
+        });
+        // This only fails if the scheduler was stopped
+        receiver.recv().unwrap_or_else(|_| Instant::now())
     }
 }
 
@@ -326,8 +462,12 @@ impl Drop for Canvas {
     fn drop(&mut self) {
         // TODO: Maybe log error caused by listener thread panicking? They don't effect shutdown though, so don't unwrap them
 
-        // "_" doesn't bind
-        let _ = self.sender.send(Message::End);
+        self.scheduler.stop();
+        // This is known as the option dance
+        // https://users.rust-lang.org/t/spawn-threads-and-join-in-destructor/1613/2
+        if let Some(frame_thread) = self.frame_thread.take() {
+            let _ = frame_thread.join();
+        }
         if CANVAS_COUNT.fetch_sub(1, Ordering::AcqRel) == 1 {
             let mut out = stdout();
             //let outRef = &mut out;
@@ -337,17 +477,22 @@ impl Drop for Canvas {
                 .and(out.flush().map_err(|e| e.into() ))
                 .expect("Error de-initializing canvas");
         }
-        // This is known as the option dance
-        // https://users.rust-lang.org/t/spawn-threads-and-join-in-destructor/1613/2
-        let _ = self.listener.take().unwrap().join();
     }
 }
 
+/// The cells of a layer, which are shared between the layer and the canvas
+type Buffer = Arc<Mutex<Vec<StyledContent<char>>>>;
+
 /// The layer holds image data within a canvas
 #[derive(/*Clone, */Debug)]
 pub struct Layer<'a> {
     buf: Arc<Mutex<Vec<StyledContent<char>>>>,
     changed: Arc<Mutex<HashSet<usize>>>,
+    /// Every layer of the canvas, including this one, so that colors can be mixed
+    /// with the layers under this one
+    layers: Arc<Mutex<Vec<Buffer>>>,
+    state: DrawState,
+    saved_states: Vec<DrawState>,
     phantom: PhantomData<&'a ()>,
 }
 
@@ -359,107 +504,432 @@ impl<'a> Layer<'a> {
 }
 
 impl<'a> Layer<'a> {
-    /// Sets the color of one pixel of the layer
+    /// Sets the color of one pixel of the layer. Pixels outside of the current
+    /// clip rectangle are left alone.
     pub fn plot(&mut self, p: IPoint, color: Color) -> Result<()> {
-        todo!();
-        //p = (p.0.floor(), p.1.floor());
-        /*self.validate_iPoints(&[p])?;
-        let r = Canvas::resolution();
-        if p.0 > r.0 || p.1 > r.1 {
-            panic!("Cannot draw outside of bounds");
-        }
-        let i = (p.1 as f32 * PX_SIZE.1) as usize * terminal::size().expect("Unable to get terminal size").0 as usize + (p.0 as f32 * PX_SIZE.0) as usize;
-        //let i = iPoint_to_index(&p);
-        self.changed.borrow_mut()[i] = true;
-        let x = p.0 as f32 * PX_SIZE.0;
-        let y = p.1 as f32 * PX_SIZE.1;
-        overlay(&mut self.buf[i], &style(
-            if PX_SIZE.0 == 0.5 {
-                if x % 1.0 == 0.0 {
-                    '▌'
-                }
-                else {
-                    '▐'
-                }
+        self.validate_i_points(&[p])?;
+        if let Some(clip) = self.state.clip {
+            if !clip.contains(p) {
+                return Ok(());
             }
-            else if PX_SIZE.1 == 0.5 {
-                if y % 1.0 == 0.0 {
-                    '▀'
-                }
-                else {
-                    '▄'
+        }
+        self.set_px(p, Some(color))
+    }
+
+    /// Sets a pixel without checking bounds or clipping. `None` makes it transparent.
+    fn set_px(&mut self, p: IPoint, color: Option<Color>) -> Result<()> {
+        let cols = terminal::size()?.0;
+        let (i, sub) = cell::locate(p, cols);
+        let mut buf = self.buf.lock()?;
+        // The buffer can be smaller than the terminal if it was resized
+        if i >= buf.len() {
+            return Ok(());
+        }
+        let mut changed = self.changed.lock()?;
+        changed.extend(cell::split_wide(&mut buf, i, cols));
+        let mut px = cell::decode(&buf[i]);
+        px[sub] = color;
+        buf[i] = cell::encode(&px);
+        changed.insert(i);
+        Ok(())
+    }
+
+    /// Writes a character into a cell, without checking bounds. Cells outside of
+    /// the clip rectangle are left alone, and box-drawing characters are joined
+    /// with the lines already in the cell. If the character has no background
+    /// color, it keeps the background of the cell.
+    ///
+    /// A wide character also covers the next cell, so it is left out if it's in
+    /// the last column, where there's no room for it.
+    fn set_cell(&mut self, cell: IPoint, c: StyledContent<char>) -> Result<()> {
+        let (cw, ch) = cell::px_per_cell();
+        if let Some(clip) = self.state.clip {
+            let px = Rect::new(cell.0 * cw, cell.1 * ch, cw, ch);
+            let visible = clip.intersection(&px);
+            if visible.width == 0 || visible.height == 0 {
+                return Ok(());
+            }
+        }
+        let cols = terminal::size()?.0;
+        let i = cell.1 as usize * cols as usize + cell.0 as usize;
+        let mut buf = self.buf.lock()?;
+        let wide = cell::is_wide(&c);
+        // The buffer can be smaller than the terminal if it was resized
+        if i >= buf.len() || (wide && (cell.0 + 1 >= cols || i + 1 >= buf.len())) {
+            return Ok(());
+        }
+        let mut changed = self.changed.lock()?;
+        let mut c = c;
+        if c.style().background_color.is_none() {
+            c.style_mut().background_color = cell::background(&buf[i]);
+        }
+        // The cell after a wide character is written first, so that splitting up a
+        // wide character that was already there doesn't blank the new one
+        if wide {
+            changed.extend(cell::split_wide(&mut buf, i + 1, cols));
+            buf[i + 1] = StyledContent::new(*c.style(), cell::CONTINUATION);
+            changed.insert(i + 1);
+        }
+        changed.extend(cell::split_wide(&mut buf, i, cols));
+        buf[i] = match boxes::merge(*buf[i].content(), *c.content()) {
+            Some(merged) => StyledContent::new(*c.style(), merged),
+            None => c,
+        };
+        changed.insert(i);
+        Ok(())
+    }
+
+    /// Gets the color of one pixel of the layer, or `None` if it is transparent
+    /// or out of bounds
+    pub fn get_pixel(&self, p: IPoint) -> Option<Color> {
+        let resolution = self.resolution();
+        if p.0 as usize >= resolution.0 || p.1 as usize >= resolution.1 {
+            return None;
+        }
+        let (i, sub) = cell::locate(p, terminal::size().ok()?.0);
+        // Reading can't leave the buffer in a bad state, so a poisoned lock doesn't matter here
+        let buf = self.buf.lock().unwrap_or_else(PoisonError::into_inner);
+        buf.get(i).and_then(|c| cell::decode(c)[sub])
+    }
+
+    /// Gets the color that shows through a transparent pixel of this layer, which is
+    /// the color of the highest layer under this one that isn't transparent there
+    fn color_below(&self, p: IPoint) -> Option<Color> {
+        let (i, sub) = cell::locate(p, terminal::size().ok()?.0);
+        // The list is copied, so that only one buffer is locked at a time
+        let layers = self.layers.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        pixel_below(&layers, &self.buf, i, sub)
+    }
+
+    /// Returns an iterator over every pixel of the layer and its color, going
+    /// left to right, then top to bottom. 
+    /// 
+    /// The layer's buffer stays locked until the iterator is dropped, so it can't
+    /// be drawn to the screen in the meantime.
+    pub fn pixels(&self) -> Pixels<'_> {
+        let (width, height) = self.resolution();
+        Pixels {
+            buf: self.buf.lock().unwrap_or_else(PoisonError::into_inner),
+            cols: terminal::size().map(|size| size.0).unwrap_or(0),
+            width: width as u16,
+            height: height as u16,
+            next: (0, 0),
+        }
+    }
+
+    /// Makes the entire layer transparent, ignoring the clip rectangle
+    pub fn clear(&mut self) -> Result<()> {
+        let mut buf = self.buf.lock()?;
+        let mut changed = self.changed.lock()?;
+        for (i, c) in buf.iter_mut().enumerate() {
+            *c = StyledContent::new(ContentStyle::new(), ' ');
+            changed.insert(i);
+        }
+        Ok(())
+    }
+
+    /// Makes a rectangle of the layer transparent
+    pub fn clear_rect(&mut self, x: u16, y: u16, width: u16, height: u16) -> Result<()> {
+        let bounds = self.transform_rect(x, y, width, height)?;
+        if !self.state.transform.is_axis_aligned() {
+            // The same pixels are cleared as would be filled by `fill_rect`
+            let corners = rect_corners(&self.state.transform, x, y, width, height);
+            let (width, height) = self.resolution();
+            for span in raster::fill_spans(&[corners], FillRule::NonZero, width as u16, height as u16) {
+                for x in span.x0..span.x1 {
+                    self.clear_px((x, span.y))?;
                 }
             }
-            else {
-                '█'
+            return Ok(());
+        }
+        let (x, y, width, height) = bounds;
+        for y in y..(y + height) {
+            for x in x..(x + width) {
+                self.clear_px((x, y))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Makes a pixel transparent, unless it is outside of the clip rectangle
+    fn clear_px(&mut self, p: IPoint) -> Result<()> {
+        if let Some(clip) = self.state.clip {
+            if !clip.contains(p) {
+                return Ok(());
             }
-        )
-            .with(color));
-        Ok(())*/
+        }
+        self.set_px(p, None)
     }
 
     fn resolution(&self) -> (usize, usize) {
         let res = Canvas::resolution();
-        (res.0 as usize, res.0 as usize)
-    }
-
-    /// Draws a line connecting points `p0` and `p1`
-    pub fn line(&mut self, p0: FPoint, p1: FPoint, color: Color) -> Result<()> {
-        // Bresenham's Line algorithm with sub-pixel precision is here
-        // https://stackoverflow.com/questions/41195973/how-to-use-bresenhams-line-drawing-algorithm-with-sub-pixel-bias
-        // I'm not using it because having a high precision (a high `scale`), the loop
-        // runs like a bazillion times, so it doesn't matter how efficient the algorithm
-        // is, it's not that much faster than DDA. DDA is a slightly slower algorithm, 
-        // but it's easier, and loops fewer times (far fewer with a high precision).
-        // Maybe I could even modify DDA to keep track of how far off it is to be self
-        // correcting (because DDA becomes offset from correct after a long distance)
-        self.validate_fPoints(&[p0, p1])?;
-        let dx = p1.0 - p0.0;
-        let dy = p1.1 - p0.1;
-        let steps;
-        let mut x;
-        let mut y;
-        if dx.abs() > dy.abs() {
-            x = p0.0.floor() + 0.5;
-            y = p0.1 + (p0.0 - x) * (dy / dx);
-            steps = dx.abs();
-        } 
-        else {
-            y = p0.1.floor() + 0.5;
-            x = p0.0 + (p0.1 - y) * (dx / dy);
-            steps = dy.abs();
+        (res.0 as usize, res.1 as usize)
+    }
+
+    /// Pushes the current drawing state onto a stack, so it can be brought back later
+    /// with `restore`
+    pub fn save(&mut self) {
+        self.saved_states.push(self.state.clone());
+    }
+
+    /// Replaces the current drawing state with the one most recently saved with `save`.
+    /// If there is no saved state, this does nothing.
+    pub fn restore(&mut self) {
+        if let Some(state) = self.saved_states.pop() {
+            self.state = state;
         }
-        let x_step = dx / steps;
-        let y_step = dy / steps;
-        for _ in 0..=steps.round() as i32 {
+    }
+
+    /// Gets the current drawing state
+    pub fn state(&self) -> &DrawState {
+        &self.state
+    }
+
+    /// Gets the current drawing state, so that it can be changed
+    pub fn state_mut(&mut self) -> &mut DrawState {
+        &mut self.state
+    }
+
+    /// Moves everything drawn after this by `(x, y)`
+    pub fn translate(&mut self, x: f32, y: f32) {
+        self.state.transform = self.state.transform.then(&Transform::translation(x, y));
+    }
+
+    /// Scales everything drawn after this by `x` horizontally and `y` vertically
+    pub fn scale(&mut self, x: f32, y: f32) {
+        self.state.transform = self.state.transform.then(&Transform::scaling(x, y));
+    }
+
+    /// Rotates everything drawn after this clockwise by `angle` radians
+    pub fn rotate(&mut self, angle: f32) {
+        self.state.transform = self.state.transform.then(&Transform::rotation(angle));
+    }
+
+    /// Shrinks the clip rectangle to its intersection with `rect`, so that nothing
+    /// is drawn outside of either of them
+    pub fn clip(&mut self, rect: Rect) {
+        self.state.clip = Some(match self.state.clip {
+            Some(clip) => clip.intersection(&rect),
+            None => rect,
+        });
+    }
+
+    /// Draws a line connecting points `p0` and `p1`. If `color` is `None`, the
+    /// current stroke color is used.
+    /// 
+    /// If the current line width is more than one pixel, the line is drawn as a
+    /// filled rectangle. Otherwise, it goes through the pixels containing `p0` and `p1`.
+    pub fn line(&mut self, p0: FPoint, p1: FPoint, color: impl Into<Option<Color>>) -> Result<()> {
+        let color = color.into().unwrap_or(self.state.stroke_color);
+        let p0 = self.state.transform.apply(p0);
+        let p1 = self.state.transform.apply(p1);
+        self.validate_f_points(&[p0, p1])?;
+        self.stroke_px(vec![(vec![p0, p1], false)], color)
+    }
+
+    /// Draws a one pixel wide line whose points have already been transformed and
+    /// validated. Thick lines are filled by `stroke_px` instead.
+    fn line_px(&mut self, p0: FPoint, p1: FPoint, color: Color) -> Result<()> {
+        let p0 = (p0.0.floor() as i32, p0.1.floor() as i32);
+        let p1 = (p1.0.floor() as i32, p1.1.floor() as i32);
+        for (x, y) in raster::bresenham(p0, p1) {
             self.plot((x as u16, y as u16), color)?;
-            x += x_step;
-            y += y_step;
         }
         Ok(())
     }
 
-    /// Draws and fills a rectangle to the layer
-    pub fn fill_rect(&mut self, x: u16, y: u16, width: u16, height: u16, color: Color) -> Result<()> {
-        self.validate_iPoints(&[(x, y), (x + width, y + height)])?;
+    /// Draws a line connecting pixels `p0` and `p1`. If `color` is `None`, the
+    /// current stroke color is used.
+    pub fn int_line(&mut self, p0: IPoint, p1: IPoint, color: impl Into<Option<Color>>) -> Result<()> {
+        let center = |p: IPoint| (p.0 as f32 + 0.5, p.1 as f32 + 0.5);
+        self.line(center(p0), center(p1), color)
+    }
+
+    /// Fills a polygon, whose points have already been transformed. Any part of it
+    /// that is outside of the layer is skipped.
+    fn fill_polygon_px(&mut self, polygon: &[FPoint], rule: FillRule, paint: &Paint) -> Result<()> {
+        self.fill_contours_px(&[polygon], rule, paint)
+    }
+
+    /// Fills a shape made up of several polygons, such as one with holes in it
+    fn fill_contours_px<P: AsRef<[FPoint]>>(&mut self, contours: &[P], rule: FillRule, paint: &Paint) -> Result<()> {
+        let (width, height) = self.resolution();
+        let shader = Shader::new(paint, &self.state.transform);
+        for span in raster::fill_spans(contours, rule, width as u16, height as u16) {
+            for x in span.x0..span.x1 {
+                self.plot_shaded((x, span.y), &shader)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Plots a pixel with the color a shader gives it. If it doesn't give one, the
+    /// pixel is left alone.
+    fn plot_shaded(&mut self, p: IPoint, shader: &Shader<'_>) -> Result<()> {
+        match shader.color_at(p) {
+            Some(color) => self.plot(p, color),
+            None => Ok(()),
+        }
+    }
+
+    /// Draws the outline of a rectangle, along its outermost pixels, using the current
+    /// line width. If `color` is `None`, the current stroke color is used.
+    pub fn stroke_rect(&mut self, x: u16, y: u16, width: u16, height: u16, color: impl Into<Option<Color>>) -> Result<()> {
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        let (left, top) = (x as f32 + 0.5, y as f32 + 0.5);
+        let (right, bottom) = (left + width as f32 - 1.0, top + height as f32 - 1.0);
+        self.stroke_polygon(&[(left, top), (right, top), (right, bottom), (left, bottom)], color)
+    }
+
+    /// Draws and fills a rectangle to the layer. If `paint` is `None`, the current
+    /// fill paint is used.
+    pub fn fill_rect(&mut self, x: u16, y: u16, width: u16, height: u16, paint: impl IntoPaint) -> Result<()> {
+        let paint = self.fill_paint(paint);
+        let bounds = self.transform_rect(x, y, width, height)?;
+        if !self.state.transform.is_axis_aligned() {
+            let corners = rect_corners(&self.state.transform, x, y, width, height);
+            return self.fill_polygon_px(&corners, FillRule::NonZero, &paint);
+        }
+        let shader = Shader::new(&paint, &self.state.transform);
+        let (x, y, width, height) = bounds;
         for y in y..(y + height) {
             for x in x..(x + width) {
-                self.plot((x, y), color)?;
+                self.plot_shaded((x, y), &shader)?;
             }
         }
         Ok(())
     }
 
+    /// Gets the paint a fill function was given, or the current fill paint if it
+    /// wasn't given one
+    fn fill_paint(&self, paint: impl IntoPaint) -> Paint {
+        paint.into_paint().unwrap_or_else(|| self.state.fill.clone())
+    }
+
+    /// Decodes an image and draws it with its top left corner at `(x, y)`. The format
+    /// is found from the first few bytes, so see `ImageFormat` for which ones can be
+    /// used. Transparent pixels, and pixels that are the color key in the drawing
+    /// state, leave the layer as it is, and partly transparent pixels are mixed with it.
     pub fn draw_img(&mut self, x: u16, y: u16, src: &mut impl Read) -> Result<()> {
-        let img = bmp::from_reader(src)?;
-        for px in img.coordinates() {
-            let px_color = img.get_pixel(px.0, px.1);
-            self.plot((x + px.0 as u16, y + px.1 as u16), Color::Rgb { r: px_color.r, g: px_color.g, b: px_color.b })?;
+        self.draw_image(x, y, &RgbaImage::from_reader(src)?)
+    }
+
+    /// Draws an image that has already been decoded, the same way as `draw_img`, so
+    /// that an image drawn many times only needs to be decoded once
+    pub fn draw_image(&mut self, x: u16, y: u16, image: &RgbaImage) -> Result<()> {
+        self.validate_image(x, y, image.width(), image.height())?;
+        for py in 0..image.height() {
+            for px in 0..image.width() {
+                self.draw_img_px((x as i32 + px as i32, y as i32 + py as i32), image.get_pixel(px, py))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes an image and draws it scaled to fit in a rectangle of pixels, so that
+    /// images of any size can be shown. The image is centered in the rectangle, and
+    /// nothing is drawn outside of it.
+    pub fn draw_img_scaled(&mut self, rect: Rect, src: &mut impl Read, fit: FitMode, filter: Filter) -> Result<()> {
+        self.validate_rect(rect.x, rect.y, rect.width, rect.height)?;
+        let img = self.decode_img(src)?;
+        let (width, height) = fit.size((img.width(), img.height()), (rect.width as u32, rect.height as u32));
+        // With `FitMode::Cover` and `FitMode::None`, the image can be bigger than the
+        // rectangle, so only its middle is drawn. Only that part is scaled, since a
+        // long, thin image can be far bigger than the rectangle once it covers it.
+        let left = width.saturating_sub(rect.width as u32) / 2;
+        let top = height.saturating_sub(rect.height as u32) / 2;
+        let (visible_width, visible_height) = (width.min(rect.width as u32), height.min(rect.height as u32));
+        let (img, left, top) = if (width, height) == (img.width(), img.height()) {
+            (img, left, top)
+        }
+        else {
+            (img.resize_part((width, height), (left, top, visible_width, visible_height), filter), 0, 0)
+        };
+        let x = rect.x as i32 + (rect.width as u32 - visible_width) as i32 / 2;
+        let y = rect.y as i32 + (rect.height as u32 - visible_height) as i32 / 2;
+        for py in 0..visible_height {
+            for px in 0..visible_width {
+                self.draw_img_px((x + px as i32, y + py as i32), img.get_pixel(left + px, top + py))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes an image to be drawn, making the pixels that are the color key transparent.
+    /// Scaled images need this done before they're scaled, so that the color key
+    /// isn't mixed into the pixels around it.
+    fn decode_img(&self, src: &mut impl Read) -> Result<RgbaImage> {
+        let mut img = RgbaImage::from_reader(src)?;
+        if let Some(key) = self.state.color_key {
+            img.apply_color_key(key);
+        }
+        Ok(img)
+    }
+
+    /// Draws one pixel of an image. Transparent pixels and pixels that are the color
+    /// key are skipped, and partly transparent ones are mixed with what's already there.
+    /// Images should be checked with `validate_image` first, so that they aren't
+    /// partly drawn before a pixel out of bounds is found.
+    fn draw_img_px(&mut self, p: (i32, i32), [r, g, b, a]: [u8; 4]) -> Result<()> {
+        let (width, height) = self.resolution();
+        if p.0 < 0 || p.1 < 0 || p.0 as usize >= width || p.1 as usize >= height {
+            return Err(ErrorKind::InvalidPoint(p.0 as f32, p.1 as f32));
+        }
+        if self.state.color_key.and_then(color::to_rgb) == Some((r, g, b)) {
+            return Ok(());
+        }
+        match a {
+            0 => Ok(()),
+            255 => self.plot((p.0 as u16, p.1 as u16), Color::Rgb { r, g, b }),
+            _ => self.blend(p, Color::Rgb { r, g, b }, a as f32 / 255.0),
+        }
+    }
+
+    /// Maps a rectangle through the current transform, and checks that all of it is
+    /// in bounds. If the transform rotates or skews the rectangle, this returns the
+    /// smallest rectangle that contains it.
+    fn transform_rect(&self, x: u16, y: u16, width: u16, height: u16) -> Result<(u16, u16, u16, u16)> {
+        transform_bounds(&self.state.transform, (x, y, width, height), self.resolution())
+    }
+
+    /// Checks that every pixel of a rectangle is in bounds. The right and bottom
+    /// edges are exclusive, so a rectangle can reach the edge of the layer.
+    fn validate_rect(&self, x: u16, y: u16, width: u16, height: u16) -> Result<()> {
+        let resolution = self.resolution();
+        let right = x as usize + width as usize;
+        let bottom = y as usize + height as usize;
+        if right > resolution.0 || bottom > resolution.1 {
+            return Err(ErrorKind::InvalidPoint(right as f32, bottom as f32));
+        }
+        Ok(())
+    }
+
+    /// Checks that every pixel of an image is in bounds, when its top left corner is
+    /// at `(x, y)`. Images can be bigger than a `Rect`, so this doesn't use `validate_rect`.
+    fn validate_image(&self, x: u16, y: u16, width: u32, height: u32) -> Result<()> {
+        let resolution = self.resolution();
+        let right = x as u64 + width as u64;
+        let bottom = y as u64 + height as u64;
+        if right > resolution.0 as u64 || bottom > resolution.1 as u64 {
+            return Err(ErrorKind::InvalidPoint(right as f32, bottom as f32));
+        }
+        Ok(())
+    }
+
+    /// Checks that every cell of a rectangle of cells is in bounds
+    fn validate_cell_rect(&self, rect: Rect) -> Result<()> {
+        let (cols, rows) = terminal::size()?;
+        let right = rect.x as usize + rect.width as usize;
+        let bottom = rect.y as usize + rect.height as usize;
+        if right > cols as usize || bottom > rows as usize {
+            return Err(ErrorKind::InvalidPoint(right as f32, bottom as f32));
         }
         Ok(())
     }
 
-    fn validate_fPoints(&self, points: &[FPoint]) -> Result<()> {
+    fn validate_f_points(&self, points: &[FPoint]) -> Result<()> {
         let resolution = self.resolution();
         let resolution = (resolution.0 as f32, resolution.1 as f32);
         for point in points {
@@ -470,11 +940,11 @@ impl<'a> Layer<'a> {
         Ok(())
     }
 
-    fn validate_iPoints(&self, points: &[IPoint]) -> Result<()> {
+    fn validate_i_points(&self, points: &[IPoint]) -> Result<()> {
         let resolution = self.resolution();
         let resolution = (resolution.0 as u16, resolution.1 as u16);
         for point in points {
-            if point.0 < 0 || point.0 >= resolution.0 || point.1 < 0 || point.1 >= resolution.1 {
+            if point.0 >= resolution.0 || point.1 >= resolution.1 {
                 return Err(ErrorKind::InvalidPoint(point.0 as f32, point.1 as f32))
             }
         }
@@ -482,6 +952,29 @@ impl<'a> Layer<'a> {
     }
 }
 
+/// An iterator over the pixels of a layer, created by `Layer::pixels`
+pub struct Pixels<'a> {
+    buf: MutexGuard<'a, Vec<StyledContent<char>>>,
+    cols: u16,
+    width: u16,
+    height: u16,
+    next: IPoint,
+}
+
+impl<'a> Iterator for Pixels<'a> {
+    type Item = (IPoint, Option<Color>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let p = self.next;
+        if p.1 >= self.height || self.width == 0 {
+            return None;
+        }
+        self.next = if p.0 + 1 < self.width { (p.0 + 1, p.1) } else { (0, p.1 + 1) };
+        let (i, sub) = cell::locate(p, self.cols);
+        Some((p, self.buf.get(i).and_then(|c| cell::decode(c)[sub])))
+    }
+}
+
 // NOTE: Methods that need integer points can take arguments of type Point<impl Integer>
 // Should I add Copy trait bound?
 /*pub trait Point<T> {
@@ -513,10 +1006,148 @@ trait ToU16: Zero + One + PartialEq + Copy {
     }
 }*/
 
-/// Point type
+/// A point that can be between pixels
 pub type FPoint = (f32, f32);
+
+/// The position of a pixel
 pub type IPoint = (u16, u16);
 
+/// The rule used to decide which parts of a shape are inside of it, when its
+/// edges cross each other or it has holes
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum FillRule {
+    /// A point is inside if the edges wind around it at all, counting edges going
+    /// up as -1 and edges going down as +1
+    NonZero,
+
+    /// A point is inside if a ray from it crosses an odd number of edges
+    EvenOdd,
+}
+
+impl FillRule {
+    /// Whether a point that the edges wind around `winding` times is filled
+    pub(crate) fn fills(&self, winding: i32) -> bool {
+        match self {
+            FillRule::NonZero => winding != 0,
+            FillRule::EvenOdd => winding % 2 != 0,
+        }
+    }
+}
+
+/// The radius of each corner of a rounded rectangle
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct CornerRadii {
+    /// The radius of the top left corner
+    pub top_left: f32,
+
+    /// The radius of the top right corner
+    pub top_right: f32,
+
+    /// The radius of the bottom right corner
+    pub bottom_right: f32,
+
+    /// The radius of the bottom left corner
+    pub bottom_left: f32,
+}
+
+impl CornerRadii {
+    /// Creates radii for each corner, going clockwise from the top left
+    pub fn new(top_left: f32, top_right: f32, bottom_right: f32, bottom_left: f32) -> Self {
+        Self { top_left, top_right, bottom_right, bottom_left }
+    }
+}
+
+impl From<f32> for CornerRadii {
+    fn from(radius: f32) -> Self {
+        Self::new(radius, radius, radius, radius)
+    }
+}
+
+/// A rectangle of pixels
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Rect {
+    /// The x coordinate of the left edge
+    pub x: u16,
+
+    /// The y coordinate of the top edge
+    pub y: u16,
+
+    /// The width of the rectangle
+    pub width: u16,
+
+    /// The height of the rectangle
+    pub height: u16,
+}
+
+impl Rect {
+    /// Creates a new rectangle
+    pub fn new(x: u16, y: u16, width: u16, height: u16) -> Self {
+        Self { x, y, width, height }
+    }
+
+    /// Whether the point is inside of the rectangle
+    pub fn contains(&self, p: IPoint) -> bool {
+        p.0 >= self.x && (p.0 as u32) < self.x as u32 + self.width as u32
+            && p.1 >= self.y && (p.1 as u32) < self.y as u32 + self.height as u32
+    }
+
+    /// Gets the part of this rectangle that is also inside of `other`. If they
+    /// don't overlap, the result has a width or height of 0.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let right = (self.x as u32 + self.width as u32).min(other.x as u32 + other.width as u32);
+        let bottom = (self.y as u32 + self.height as u32).min(other.y as u32 + other.height as u32);
+        Rect {
+            x,
+            y,
+            width: right.saturating_sub(x as u32) as u16,
+            height: bottom.saturating_sub(y as u32) as u16,
+        }
+    }
+}
+
+/// Gets the color of pixel `sub` of cell `i` in the highest layer under `layer`
+/// that isn't transparent there. `layers` goes from the bottom up.
+fn pixel_below(layers: &[Buffer], layer: &Buffer, i: usize, sub: usize) -> Option<Color> {
+    let index = layers.iter().position(|other| Arc::ptr_eq(other, layer))?;
+    layers[..index].iter().rev().find_map(|below| {
+        let buf = below.lock().unwrap_or_else(PoisonError::into_inner);
+        buf.get(i).and_then(|c| cell::decode(c)[sub])
+    })
+}
+
+/// Gets the corners of a rectangle mapped through a transform, going clockwise from
+/// the top left
+fn rect_corners(transform: &Transform, x: u16, y: u16, width: u16, height: u16) -> [FPoint; 4] {
+    let (x, y, width, height) = (x as f32, y as f32, width as f32, height as f32);
+    [
+        transform.apply((x, y)),
+        transform.apply((x + width, y)),
+        transform.apply((x + width, y + height)),
+        transform.apply((x, y + height)),
+    ]
+}
+
+/// Maps a rectangle through a transform and rounds it out to whole pixels. The
+/// bounds are found before anything is converted to `u16`, so that a rectangle
+/// that is moved off of the top or left of the layer is an error instead of being
+/// squashed against the edge.
+fn transform_bounds(transform: &Transform, (x, y, width, height): (u16, u16, u16, u16), resolution: (usize, usize)) -> Result<(u16, u16, u16, u16)> {
+    let corners = rect_corners(transform, x, y, width, height);
+    let left = corners.iter().map(|p| p.0).fold(f32::INFINITY, f32::min).round();
+    let top = corners.iter().map(|p| p.1).fold(f32::INFINITY, f32::min).round();
+    let right = corners.iter().map(|p| p.0).fold(f32::NEG_INFINITY, f32::max).round();
+    let bottom = corners.iter().map(|p| p.1).fold(f32::NEG_INFINITY, f32::max).round();
+    if !left.is_finite() || !top.is_finite() || left < 0.0 || top < 0.0 {
+        return Err(ErrorKind::InvalidPoint(left, top));
+    }
+    if !right.is_finite() || !bottom.is_finite() || right > resolution.0 as f32 || bottom > resolution.1 as f32 {
+        return Err(ErrorKind::InvalidPoint(right, bottom));
+    }
+    Ok((left as u16, top as u16, (right - left) as u16, (bottom - top) as u16))
+}
+
 /*fn fPoint_to_index(p: &FPoint) -> usize {
     let r = Canvas::resolution();
     if p.0 > r.0.into() || p.1 > r.1.into() {
@@ -534,36 +1165,80 @@ fn iPoint_to_index(p: &IPoint) -> usize {
 }*/
 
 
+/// Composites the cell at index `i` of each layer, where the layers go from the
+/// top down
+fn composite<L: Deref<Target = Vec<StyledContent<char>>>>(layers: &[L], i: usize) -> StyledContent<char> {
+    let mut c = StyledContent::new(ContentStyle::new(), ' ');
+    for layer in layers.iter() {
+        underlay(&mut c, &layer[i]);
+        if !underlay_possible(&c) {
+            break;
+        }
+    }
+    c
+}
+
+/// Gets what to print at index `i`, after compositing the layers. `None` means
+/// nothing is printed, since the cell is covered by the wide character before it.
+///
+/// When one layer covers half of a wide character from a layer under it, the other
+/// half can't be printed on its own, so it is replaced with a space.
+fn present<L: Deref<Target = Vec<StyledContent<char>>>>(layers: &[L], i: usize, cols: u16) -> Option<StyledContent<char>> {
+    let c = composite(layers, i);
+    let col = i % cols as usize;
+    if *c.content() == cell::CONTINUATION {
+        if col > 0 && cell::is_wide(&composite(layers, i - 1)) {
+            return None;
+        }
+        return Some(cell::blank(&c));
+    }
+    if cell::is_wide(&c) && (col + 1 >= cols as usize || *composite(layers, i + 1).content() != cell::CONTINUATION) {
+        return Some(cell::blank(&c));
+    }
+    Some(c)
+}
+
 /// Overlays c2 over c1, storing the result in c1
 fn overlay(c1: &mut StyledContent<char>, c2: &StyledContent<char>) {
     if !underlay_possible(c2) {
         *c1 = *c2
     }
-    else if c1.content() == c2.content() || ('─'..='╬').contains(c1.content()) {
-        let s1 = c1.style_mut();
-        let s2 = c2.style();
-        if s2.foreground_color.is_some() {
-            s1.foreground_color = s2.foreground_color;
-        }
-        if s2.background_color.is_some() {
-            s1.background_color = s2.background_color;
-        }
-        //let lines = [['─', '━', '═'], ]
-        if ![' ', '█'].contains(c2.content()) {
-            todo!()
-        }
+    else if c1.content() == c2.content() {
+        merge_style(c1.style_mut(), c2.style());
+    }
+    // Lines drawn on different layers are joined, so │ over ─ makes ┼
+    else if let Some(merged) = boxes::merge(*c1.content(), *c2.content()) {
+        let mut style = *c1.style();
+        merge_style(&mut style, c2.style());
+        *c1 = StyledContent::new(style, merged);
+    }
+    // Text replaces the glyph under it, but the color behind it stays
+    else if !cell::is_block(*c2.content()) {
+        let background = cell::background(c1);
+        *c1 = *c2;
+        c1.style_mut().background_color = background;
     }
     // if c2 is ' ' or '', and it can be underlayed, c2 is entirely transparent,
     // so c1 stays the same
     else if ![' ', '█'].contains(c2.content()) {
         match if c1.content() < c2.content() { (c1.content(), c2.content()) } else { (c2.content(), c1.content()) } {
-            ('▌', '▐') | ('▀', '▄') => (*c1.style_mut()).background_color = c2.style().foreground_color,
+            ('▌', '▐') | ('▀', '▄') => c1.style_mut().background_color = c2.style().foreground_color,
             //('─', '━') => *c1 = StyledContent::new(c1.style()),
             _ => *c1 = *c2
         }
     }
 }
 
+/// Copies the colors that are set in s2 into s1
+fn merge_style(s1: &mut ContentStyle, s2: &ContentStyle) {
+    if s2.foreground_color.is_some() {
+        s1.foreground_color = s2.foreground_color;
+    }
+    if s2.background_color.is_some() {
+        s1.background_color = s2.background_color;
+    }
+}
+
 /// Overlays c1 over c2, storing the result in c1
 fn underlay(c1: &mut StyledContent<char>, c2: &StyledContent<char>) {
     let mut c2 = *c2;
@@ -596,7 +1271,10 @@ fn get_winsize() -> Result<winsize> {
     let w = winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
     let r = unsafe { ioctl(STDOUT_FILENO, TIOCGWINSZ, &w) };
 
-    debug_assert_eq!((w.ws_col, w.ws_row), terminal::size().unwrap());
+    // Without a terminal (such as when running tests), there's nothing to compare with
+    if r == 0 {
+        debug_assert_eq!((w.ws_col, w.ws_row), terminal::size().unwrap());
+    }
 
     if r == 0 && w.ws_xpixel > 0 && w.ws_ypixel > 0 && w.ws_col > 0 && w.ws_row > 0 {
         Ok(w)
@@ -605,3 +1283,63 @@ fn get_winsize() -> Result<winsize> {
         Err(io::Error::from(io::ErrorKind::Other).into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RESOLUTION: (usize, usize) = (80, 48);
+
+    #[test]
+    fn identity_bounds_are_unchanged() {
+        assert_eq!(transform_bounds(&Transform::IDENTITY, (3, 4, 10, 5), RESOLUTION).unwrap(), (3, 4, 10, 5));
+        assert_eq!(transform_bounds(&Transform::IDENTITY, (70, 40, 10, 8), RESOLUTION).unwrap(), (70, 40, 10, 8));
+    }
+
+    #[test]
+    fn negative_bounds_are_an_error() {
+        let t = Transform::translation(-5.0, 0.0);
+        assert!(transform_bounds(&t, (0, 0, 10, 10), RESOLUTION).is_err());
+        assert_eq!(transform_bounds(&t, (5, 0, 10, 10), RESOLUTION).unwrap(), (0, 0, 10, 10));
+        assert!(transform_bounds(&Transform::scaling(-1.0, 1.0), (0, 0, 1, 1), RESOLUTION).is_err());
+    }
+
+    #[test]
+    fn out_of_range_bounds_are_an_error() {
+        assert!(transform_bounds(&Transform::IDENTITY, (75, 0, 10, 10), RESOLUTION).is_err());
+        assert!(transform_bounds(&Transform::scaling(100.0, 1.0), (1, 1, 1, 1), RESOLUTION).is_err());
+        assert!(transform_bounds(&Transform::translation(f32::NAN, 0.0), (1, 1, 1, 1), RESOLUTION).is_err());
+    }
+
+    #[test]
+    fn rotated_bounds_contain_corners() {
+        let t = Transform::translation(20.0, 20.0).then(&Transform::rotation(std::f32::consts::FRAC_PI_4));
+        let (x, y, width, height) = transform_bounds(&t, (0, 0, 10, 10), RESOLUTION).unwrap();
+        for &(cx, cy) in rect_corners(&t, 0, 0, 10, 10).iter() {
+            assert!(cx >= x as f32 - 0.5 && cx <= (x + width) as f32 + 0.5);
+            assert!(cy >= y as f32 - 0.5 && cy <= (y + height) as f32 + 0.5);
+        }
+        assert_eq!(width, 14);
+    }
+
+    fn buffer(color: Option<Color>) -> Buffer {
+        let mut style = ContentStyle::new();
+        style.background_color = color;
+        Arc::new(Mutex::new(vec![StyledContent::new(style, ' '); 4]))
+    }
+
+    #[test]
+    fn pixel_below_uses_highest_opaque_layer() {
+        let layers = vec![buffer(Some(Color::Red)), buffer(Some(Color::Blue)), buffer(None), buffer(None)];
+        assert_eq!(pixel_below(&layers, &layers[3], 1, 0), Some(Color::Blue));
+        assert_eq!(pixel_below(&layers, &layers[1], 1, 0), Some(Color::Red));
+    }
+
+    #[test]
+    fn nothing_below_bottom_layer() {
+        let layers = vec![buffer(None), buffer(Some(Color::Red))];
+        assert_eq!(pixel_below(&layers, &layers[0], 0, 0), None);
+        assert_eq!(pixel_below(&layers, &layers[1], 0, 0), None);
+        assert_eq!(pixel_below(&layers, &buffer(None), 0, 0), None);
+    }
+}
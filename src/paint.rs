@@ -0,0 +1,318 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module holds the types used to fill shapes with something other than a
+//! single color.
+
+use std::{cmp::Ordering, f32::consts::PI, io::Read, sync::Arc};
+use crate::{bitmap::RgbaImage, color, state::Transform, Color, FPoint, IPoint, Result};
+
+/// A list of colors at positions from 0 to 1, with the colors in between them blended
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    stops: Vec<(f32, Color)>,
+}
+
+impl Gradient {
+    /// Creates a gradient from pairs of positions (from 0 to 1) and colors. They
+    /// don't need to be in order. Before the first stop, the first color is used,
+    /// and after the last stop, the last color is used.
+    pub fn new(stops: &[(f32, Color)]) -> Self {
+        let mut stops = stops.to_vec();
+        // This is a stable sort, so stops at the same position keep their order,
+        // which makes a sudden change from one color to the next
+        stops.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        Self { stops }
+    }
+
+    /// Gets the color at position `t`
+    pub fn color_at(&self, t: f32) -> Option<Color> {
+        let first = self.stops.first()?;
+        if t.is_nan() || t <= first.0 {
+            return Some(first.1);
+        }
+        for pair in self.stops.windows(2) {
+            let ((start, a), (end, b)) = (pair[0], pair[1]);
+            if t <= end {
+                return Some(if end == start { b } else { color::mix(a, b, (t - start) / (end - start)) });
+            }
+        }
+        self.stops.last().map(|stop| stop.1)
+    }
+}
+
+/// What a pattern does past the edges of its image
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Repeat {
+    /// The image is tiled over and over
+    Repeat,
+
+    /// The pixels at the edges of the image are stretched out forever
+    Clamp,
+}
+
+/// An image used to fill shapes. Each pixel of the image covers one pixel of the
+/// layer (before the layer's transform is applied).
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pattern {
+    /// The image. It is in an `Arc` so that the paint can be cloned cheaply
+    pub image: Arc<RgbaImage>,
+
+    /// What happens past the edges of the image
+    pub repeat: Repeat,
+
+    /// Where the top left corner of the image is
+    pub offset: FPoint,
+}
+
+impl Pattern {
+    /// Creates a pattern that repeats an image, starting at (0, 0)
+    pub fn new(image: RgbaImage) -> Self {
+        Self { image: Arc::new(image), repeat: Repeat::Repeat, offset: (0.0, 0.0) }
+    }
+
+    /// Creates a pattern from an image in any of the formats in `ImageFormat`, decoded
+    /// the same way as in `Layer::draw_img`
+    pub fn from_reader(src: &mut impl Read) -> Result<Self> {
+        Ok(Self::new(RgbaImage::from_reader(src)?))
+    }
+
+    /// Creates a small pattern, such as a hatch pattern, from a list of rows, where
+    /// `None` is transparent
+    pub fn from_rows(rows: &[&[Option<Color>]]) -> Self {
+        let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+        let mut image = RgbaImage::new(width as u32, rows.len() as u32);
+        for (y, row) in rows.iter().enumerate() {
+            for (x, &color) in row.iter().enumerate() {
+                if let Some((r, g, b)) = color.and_then(color::to_rgb) {
+                    image.set_pixel(x as u32, y as u32, [r, g, b, 255]);
+                }
+            }
+        }
+        Self::new(image)
+    }
+
+    /// Gets the color at a point, before it is transformed
+    pub fn color_at(&self, p: FPoint) -> Option<Color> {
+        let (width, height) = (self.image.width() as i64, self.image.height() as i64);
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let x = (p.0 - self.offset.0).floor() as i64;
+        let y = (p.1 - self.offset.1).floor() as i64;
+        let (x, y) = match self.repeat {
+            Repeat::Repeat => (x.rem_euclid(width), y.rem_euclid(height)),
+            Repeat::Clamp => (x.max(0).min(width - 1), y.max(0).min(height - 1)),
+        };
+        self.image.color_at(x as u32, y as u32)
+    }
+}
+
+/// What a shape is filled with. The points used by gradients are mapped through the
+/// layer's transform, the same as the points of the shape.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Paint {
+    /// A single color
+    Solid(Color),
+
+    /// A gradient that changes along the line from `start` to `end`, and is the same
+    /// along lines perpendicular to it
+    Linear {
+        /// Where the gradient is at 0
+        start: FPoint,
+
+        /// Where the gradient is at 1
+        end: FPoint,
+
+        /// The colors of the gradient
+        gradient: Gradient,
+    },
+
+    /// A gradient that changes going out from `center`
+    Radial {
+        /// Where the gradient is at 0
+        center: FPoint,
+
+        /// How far from the center the gradient is at 1
+        radius: f32,
+
+        /// The colors of the gradient
+        gradient: Gradient,
+    },
+
+    /// A gradient that changes going clockwise around `center`
+    Conic {
+        /// The point the gradient goes around
+        center: FPoint,
+
+        /// The angle, in radians, where the gradient starts. 0 points to the right
+        angle: f32,
+
+        /// The colors of the gradient
+        gradient: Gradient,
+    },
+
+    /// A repeating or clamped image
+    Pattern(Pattern),
+}
+
+impl Paint {
+    /// Creates a linear gradient from `start` to `end`
+    pub fn linear(start: FPoint, end: FPoint, stops: &[(f32, Color)]) -> Self {
+        Paint::Linear { start, end, gradient: Gradient::new(stops) }
+    }
+
+    /// Creates a radial gradient around `center`
+    pub fn radial(center: FPoint, radius: f32, stops: &[(f32, Color)]) -> Self {
+        Paint::Radial { center, radius, gradient: Gradient::new(stops) }
+    }
+
+    /// Creates a conic gradient around `center`, starting at `angle` radians
+    pub fn conic(center: FPoint, angle: f32, stops: &[(f32, Color)]) -> Self {
+        Paint::Conic { center, angle, gradient: Gradient::new(stops) }
+    }
+
+    /// Gets the color at a point, before it is transformed
+    pub fn color_at(&self, p: FPoint) -> Option<Color> {
+        match self {
+            Paint::Solid(color) => Some(*color),
+            Paint::Linear { start, end, gradient } => {
+                let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+                let len_sq = dx * dx + dy * dy;
+                let t = if len_sq == 0.0 { 0.0 } else { ((p.0 - start.0) * dx + (p.1 - start.1) * dy) / len_sq };
+                gradient.color_at(t)
+            },
+            Paint::Radial { center, radius, gradient } => {
+                gradient.color_at((p.0 - center.0).hypot(p.1 - center.1) / radius)
+            },
+            Paint::Conic { center, angle, gradient } => {
+                let t = (p.1 - center.1).atan2(p.0 - center.0) - angle;
+                gradient.color_at(t.rem_euclid(2.0 * PI) / (2.0 * PI))
+            },
+            Paint::Pattern(pattern) => pattern.color_at(p),
+        }
+    }
+}
+
+impl From<Pattern> for Paint {
+    fn from(pattern: Pattern) -> Self {
+        Paint::Pattern(pattern)
+    }
+}
+
+impl From<Color> for Paint {
+    fn from(color: Color) -> Self {
+        Paint::Solid(color)
+    }
+}
+
+/// Something that a shape can be filled with. This is a `Paint`, `Pattern` or `Color`, or
+/// `None` to use the current fill paint.
+pub trait IntoPaint {
+    /// Converts this into a paint, or `None` to use the current fill paint
+    fn into_paint(self) -> Option<Paint>;
+}
+
+impl IntoPaint for Paint {
+    fn into_paint(self) -> Option<Paint> {
+        Some(self)
+    }
+}
+
+impl IntoPaint for &Paint {
+    fn into_paint(self) -> Option<Paint> {
+        Some(self.clone())
+    }
+}
+
+impl IntoPaint for Pattern {
+    fn into_paint(self) -> Option<Paint> {
+        Some(Paint::Pattern(self))
+    }
+}
+
+impl IntoPaint for Color {
+    fn into_paint(self) -> Option<Paint> {
+        Some(Paint::Solid(self))
+    }
+}
+
+impl IntoPaint for Option<Paint> {
+    fn into_paint(self) -> Option<Paint> {
+        self
+    }
+}
+
+/// Gets the colors of a paint at pixels, which have already been transformed
+pub(crate) struct Shader<'a> {
+    paint: &'a Paint,
+    /// Maps pixels back to the paint's points. A transform that can't be undone
+    /// squashes everything onto a line, so there's nothing to fill with anything
+    /// but a solid color.
+    inverse: Option<Transform>,
+}
+
+impl<'a> Shader<'a> {
+    /// Creates a shader for a paint drawn with `transform`
+    pub fn new(paint: &'a Paint, transform: &Transform) -> Self {
+        Self { paint, inverse: transform.inverse() }
+    }
+
+    /// Gets the color at the center of a pixel, or `None` if nothing should be drawn there
+    pub fn color_at(&self, p: IPoint) -> Option<Color> {
+        match self.paint {
+            Paint::Solid(color) => Some(*color),
+            paint => paint.color_at(self.inverse?.apply((p.0 as f32 + 0.5, p.1 as f32 + 0.5))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Color = Color::Rgb { r: 0, g: 0, b: 0 };
+    const WHITE: Color = Color::Rgb { r: 255, g: 255, b: 255 };
+
+    #[test]
+    fn gradient_is_clamped_at_ends() {
+        let gradient = Gradient::new(&[(1.0, WHITE), (0.0, BLACK)]);
+        assert_eq!(gradient.color_at(-1.0), Some(BLACK));
+        assert_eq!(gradient.color_at(f32::NAN), Some(BLACK));
+        assert_eq!(gradient.color_at(2.0), Some(WHITE));
+        assert_eq!(Gradient::new(&[]).color_at(0.5), None);
+    }
+
+    #[test]
+    fn gradient_stops_at_same_position_are_sudden() {
+        let red = Color::Rgb { r: 255, g: 0, b: 0 };
+        let gradient = Gradient::new(&[(0.0, BLACK), (0.5, BLACK), (0.5, red), (1.0, red)]);
+        assert_eq!(gradient.color_at(0.49), Some(BLACK));
+        assert_eq!(gradient.color_at(0.51), Some(red));
+    }
+
+    #[test]
+    fn pattern_repeats_and_clamps() {
+        let mut pattern = Pattern::from_rows(&[&[Some(BLACK), Some(WHITE)]]);
+        assert_eq!(pattern.color_at((2.5, 0.5)), Some(BLACK));
+        assert_eq!(pattern.color_at((-0.5, 3.5)), Some(WHITE));
+        pattern.repeat = Repeat::Clamp;
+        assert_eq!(pattern.color_at((5.0, -5.0)), Some(WHITE));
+    }
+
+    #[test]
+    fn shader_undoes_transform() {
+        let paint = Paint::linear((0.0, 0.0), (10.0, 0.0), &[(0.2, BLACK), (0.8, WHITE)]);
+        let shader = Shader::new(&paint, &Transform::scaling(2.0, 1.0));
+        assert_eq!(shader.color_at((3, 0)), Some(BLACK));
+        assert_eq!(shader.color_at((17, 0)), Some(WHITE));
+    }
+
+    #[test]
+    fn shader_skips_non_invertible_transform() {
+        let paint = Paint::linear((0.0, 0.0), (10.0, 0.0), &[(0.0, BLACK), (1.0, WHITE)]);
+        assert_eq!(Shader::new(&paint, &Transform::scaling(0.0, 1.0)).color_at((0, 0)), None);
+        let solid = Paint::Solid(BLACK);
+        assert_eq!(Shader::new(&solid, &Transform::scaling(0.0, 1.0)).color_at((0, 0)), Some(BLACK));
+    }
+}
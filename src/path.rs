@@ -0,0 +1,336 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module is used to draw shapes made of lines and curves.
+
+use std::f32::consts::PI;
+use crate::{paint::IntoPaint, state::Transform, Color, CornerRadii, FillRule, FPoint, Layer, Result};
+
+/// How far, in pixels, the lines a curve is broken up into can be from the real curve
+const TOLERANCE: f32 = 0.25;
+
+/// The most times a curve is split in half when flattening it
+const MAX_DEPTH: u32 = 16;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Segment {
+    MoveTo(FPoint),
+    LineTo(FPoint),
+    QuadTo(FPoint, FPoint),
+    CubicTo(FPoint, FPoint, FPoint),
+    /// An arc from the current point, which is on the circle, around `center`
+    /// for `sweep` radians (clockwise if positive)
+    Arc { center: FPoint, radius: f32, sweep: f32 },
+    Close,
+}
+
+/// A shape made of lines and curves, which can be drawn with `Layer::stroke_path`
+/// and `Layer::fill_path`. It works like a path on an HTML canvas.
+/// 
+/// ```ignore
+/// let mut path = Path::new();
+/// path.move_to((2.0, 2.0))
+///     .line_to((20.0, 2.0))
+///     .quad_to((20.0, 12.0), (10.0, 12.0))
+///     .close();
+/// layer.fill_path(&path, FillRule::NonZero, Color::Blue)?;
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Path {
+    segments: Vec<Segment>,
+    current: Option<FPoint>,
+    start: Option<FPoint>,
+}
+
+impl Path {
+    /// Creates an empty path
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts a new part of the path at `p`
+    pub fn move_to(&mut self, p: FPoint) -> &mut Self {
+        self.segments.push(Segment::MoveTo(p));
+        self.current = Some(p);
+        self.start = Some(p);
+        self
+    }
+
+    /// Adds a straight line from the current point to `p`
+    pub fn line_to(&mut self, p: FPoint) -> &mut Self {
+        if self.current.is_none() {
+            return self.move_to(p);
+        }
+        self.segments.push(Segment::LineTo(p));
+        self.current = Some(p);
+        self
+    }
+
+    /// Adds a quadratic Bézier curve from the current point to `p`
+    pub fn quad_to(&mut self, control: FPoint, p: FPoint) -> &mut Self {
+        self.ensure_started(control);
+        self.segments.push(Segment::QuadTo(control, p));
+        self.current = Some(p);
+        self
+    }
+
+    /// Adds a cubic Bézier curve from the current point to `p`
+    pub fn cubic_to(&mut self, control1: FPoint, control2: FPoint, p: FPoint) -> &mut Self {
+        self.ensure_started(control1);
+        self.segments.push(Segment::CubicTo(control1, control2, p));
+        self.current = Some(p);
+        self
+    }
+
+    /// Adds an arc with the given radius that curves from the line between the
+    /// current point and `p1` to the line between `p1` and `p2`, along with a
+    /// straight line from the current point to the start of the arc. This is the
+    /// same as HTML's `arcTo`, and is useful for rounded corners.
+    /// 
+    /// If the points are in a straight line or the radius is 0, this just adds a
+    /// line to `p1`.
+    pub fn arc_to(&mut self, p1: FPoint, p2: FPoint, radius: f32) -> &mut Self {
+        let p0 = match self.current {
+            Some(p0) => p0,
+            None => return self.move_to(p1),
+        };
+        let normalize = |v: FPoint| {
+            let len = v.0.hypot(v.1);
+            (v.0 / len, v.1 / len)
+        };
+        let v1 = normalize((p0.0 - p1.0, p0.1 - p1.1));
+        let v2 = normalize((p2.0 - p1.0, p2.1 - p1.1));
+        let cross = v1.0 * v2.1 - v1.1 * v2.0;
+        // The points being the same makes v1 or v2 NaN, so that's a straight line too
+        if radius <= 0.0 || cross.is_nan() || cross.abs() <= f32::EPSILON {
+            return self.line_to(p1);
+        }
+        let angle = (v1.0 * v2.0 + v1.1 * v2.1).acos();
+        let tangent = radius / (angle / 2.0).tan();
+        let start = (p1.0 + v1.0 * tangent, p1.1 + v1.1 * tangent);
+        let end = (p1.0 + v2.0 * tangent, p1.1 + v2.1 * tangent);
+        let bisector = normalize((v1.0 + v2.0, v1.1 + v2.1));
+        let distance = radius / (angle / 2.0).sin();
+        let center = (p1.0 + bisector.0 * distance, p1.1 + bisector.1 * distance);
+        let start_angle = (start.1 - center.1).atan2(start.0 - center.0);
+        let end_angle = (end.1 - center.1).atan2(end.0 - center.0);
+        // The arc is always the short way around
+        let sweep = (end_angle - start_angle + PI).rem_euclid(2.0 * PI) - PI;
+        self.line_to(start);
+        self.segments.push(Segment::Arc { center, radius, sweep });
+        self.current = Some(end);
+        self
+    }
+
+    /// Adds a straight line back to the start of the current part of the path,
+    /// and closes it
+    pub fn close(&mut self) -> &mut Self {
+        if self.current.is_some() {
+            self.segments.push(Segment::Close);
+            self.current = self.start;
+        }
+        self
+    }
+
+    /// Creates a closed path around a rectangle
+    pub fn rect(x: f32, y: f32, width: f32, height: f32) -> Self {
+        let mut path = Self::new();
+        path.move_to((x, y))
+            .line_to((x + width, y))
+            .line_to((x + width, y + height))
+            .line_to((x, y + height))
+            .close();
+        path
+    }
+
+    /// Creates a closed path around a rectangle with rounded corners. If the radii
+    /// of two corners on the same side add up to more than the length of that side,
+    /// all of the radii are shrunk in proportion until they fit.
+    pub fn round_rect(x: f32, y: f32, width: f32, height: f32, radii: impl Into<CornerRadii>) -> Self {
+        let r = radii.into();
+        let fit = |side: f32, a: f32, b: f32| if a + b > side { side / (a + b) } else { 1.0 };
+        let scale = fit(width, r.top_left, r.top_right)
+            .min(fit(width, r.bottom_left, r.bottom_right))
+            .min(fit(height, r.top_left, r.bottom_left))
+            .min(fit(height, r.top_right, r.bottom_right));
+        let (right, bottom) = (x + width, y + height);
+        let mut path = Self::new();
+        path.move_to((x + r.top_left * scale, y))
+            .arc_to((right, y), (right, bottom), r.top_right * scale)
+            .arc_to((right, bottom), (x, bottom), r.bottom_right * scale)
+            .arc_to((x, bottom), (x, y), r.bottom_left * scale)
+            .arc_to((x, y), (right, y), r.top_left * scale)
+            .close();
+        path
+    }
+
+    /// Curves need to start somewhere, so if there's no current point, this makes it `p`
+    fn ensure_started(&mut self, p: FPoint) {
+        if self.current.is_none() {
+            self.move_to(p);
+        }
+    }
+
+    /// Breaks the path up into lists of points, after mapping it through `transform`.
+    /// Each list comes with whether or not it was closed.
+    pub(crate) fn flatten(&self, transform: &Transform) -> Vec<(Vec<FPoint>, bool)> {
+        let mut parts = Vec::new();
+        let mut points: Vec<FPoint> = Vec::new();
+        // The current point and the start of the current part, before being transformed
+        let mut user_point = (0.0, 0.0);
+        let mut user_start = (0.0, 0.0);
+        for &segment in self.segments.iter() {
+            match segment {
+                Segment::MoveTo(p) => {
+                    if points.len() > 1 {
+                        parts.push((std::mem::take(&mut points), false));
+                    }
+                    points = vec![transform.apply(p)];
+                    user_point = p;
+                    user_start = p;
+                },
+                Segment::LineTo(p) => {
+                    points.push(transform.apply(p));
+                    user_point = p;
+                },
+                Segment::QuadTo(control, p) => {
+                    // A quadratic curve is a cubic curve with both control points two
+                    // thirds of the way to the quadratic curve's control point
+                    let p0 = user_point;
+                    let control1 = (p0.0 + (control.0 - p0.0) * 2.0 / 3.0, p0.1 + (control.1 - p0.1) * 2.0 / 3.0);
+                    let control2 = (p.0 + (control.0 - p.0) * 2.0 / 3.0, p.1 + (control.1 - p.1) * 2.0 / 3.0);
+                    let cubic = [p0, control1, control2, p].map(|p| transform.apply(p));
+                    flatten_cubic(cubic, MAX_DEPTH, &mut points);
+                    user_point = p;
+                },
+                Segment::CubicTo(control1, control2, p) => {
+                    let cubic = [user_point, control1, control2, p].map(|p| transform.apply(p));
+                    flatten_cubic(cubic, MAX_DEPTH, &mut points);
+                    user_point = p;
+                },
+                Segment::Arc { center, radius, sweep } => {
+                    let start = (user_point.1 - center.1).atan2(user_point.0 - center.0);
+                    let scaled_radius = radius * transform.scale_factor();
+                    let step = if scaled_radius > TOLERANCE { 2.0 * (1.0 - TOLERANCE / scaled_radius).acos() } else { PI };
+                    let steps = (sweep.abs() / step).ceil().max(1.0) as u32;
+                    for i in 1..=steps {
+                        let angle = start + sweep * i as f32 / steps as f32;
+                        user_point = (center.0 + radius * angle.cos(), center.1 + radius * angle.sin());
+                        points.push(transform.apply(user_point));
+                    }
+                },
+                Segment::Close => {
+                    if let Some(&first) = points.first() {
+                        parts.push((std::mem::replace(&mut points, vec![first]), true));
+                    }
+                    user_point = user_start;
+                },
+            }
+        }
+        if points.len() > 1 {
+            parts.push((points, false));
+        }
+        parts
+    }
+}
+
+/// Adds points along a cubic Bézier curve, which is already in pixel coordinates,
+/// to `points`. The first point isn't added, since it is already there. The curve
+/// is split in half until each piece is close enough to a straight line.
+fn flatten_cubic(curve: [FPoint; 4], depth: u32, points: &mut Vec<FPoint>) {
+    let [p0, p1, p2, p3] = curve;
+    if depth == 0 || (distance_to_line(p1, p0, p3) <= TOLERANCE && distance_to_line(p2, p0, p3) <= TOLERANCE) {
+        points.push(p3);
+        return;
+    }
+    let mid = |a: FPoint, b: FPoint| ((a.0 + b.0) / 2.0, (a.1 + b.1) / 2.0);
+    let p01 = mid(p0, p1);
+    let p12 = mid(p1, p2);
+    let p23 = mid(p2, p3);
+    let p012 = mid(p01, p12);
+    let p123 = mid(p12, p23);
+    let center = mid(p012, p123);
+    flatten_cubic([p0, p01, p012, center], depth - 1, points);
+    flatten_cubic([center, p123, p23, p3], depth - 1, points);
+}
+
+/// Gets the distance from `p` to the line segment from `a` to `b`
+fn distance_to_line(p: FPoint, a: FPoint, b: FPoint) -> f32 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len_sq = dx * dx + dy * dy;
+    let t = if len_sq == 0.0 { 0.0 } else { (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len_sq).clamp(0.0, 1.0) };
+    (p.0 - (a.0 + t * dx)).hypot(p.1 - (a.1 + t * dy))
+}
+
+impl<'a> Layer<'a> {
+    /// Draws and fills a rectangle with rounded corners. If `paint` is `None`, the
+    /// current fill paint is used.
+    pub fn fill_round_rect(&mut self, x: u16, y: u16, width: u16, height: u16, radii: impl Into<CornerRadii>, paint: impl IntoPaint) -> Result<()> {
+        let paint = self.fill_paint(paint);
+        self.transform_rect(x, y, width, height)?;
+        let path = Path::round_rect(x as f32, y as f32, width as f32, height as f32, radii);
+        let contours: Vec<Vec<FPoint>> = path.flatten(&self.state.transform).into_iter().map(|(points, _)| points).collect();
+        self.fill_contours_px(&contours, FillRule::NonZero, &paint)
+    }
+
+    /// Draws the outline of a rectangle with rounded corners, along its outermost
+    /// pixels, using the current line width. If `color` is `None`, the current
+    /// stroke color is used.
+    pub fn stroke_round_rect(&mut self, x: u16, y: u16, width: u16, height: u16, radii: impl Into<CornerRadii>, color: impl Into<Option<Color>>) -> Result<()> {
+        if width == 0 || height == 0 {
+            return Ok(());
+        }
+        let path = Path::round_rect(x as f32 + 0.5, y as f32 + 0.5, width as f32 - 1.0, height as f32 - 1.0, radii);
+        self.stroke_path(&path, color)
+    }
+
+    /// Draws the outline of a path, using the current line width. If `color` is
+    /// `None`, the current stroke color is used.
+    pub fn stroke_path(&mut self, path: &Path, color: impl Into<Option<Color>>) -> Result<()> {
+        let color = color.into().unwrap_or(self.state.stroke_color);
+        let parts = path.flatten(&self.state.transform);
+        for (points, _) in parts.iter() {
+            self.validate_f_points(points)?;
+        }
+        self.stroke_px(parts, color)
+    }
+
+    /// Draws and fills a path. Parts of it that aren't closed are filled as though
+    /// they were. If `paint` is `None`, the current fill paint is used.
+    pub fn fill_path(&mut self, path: &Path, rule: FillRule, paint: impl IntoPaint) -> Result<()> {
+        let paint = self.fill_paint(paint);
+        let contours: Vec<Vec<FPoint>> = path.flatten(&self.state.transform).into_iter().map(|(points, _)| points).collect();
+        for points in contours.iter() {
+            self.validate_f_points(points)?;
+        }
+        self.fill_contours_px(&contours, rule, &paint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distance_is_to_nearest_end_past_segment() {
+        assert_eq!(distance_to_line((5.0, 3.0), (0.0, 0.0), (10.0, 0.0)), 3.0);
+        assert_eq!(distance_to_line((13.0, 4.0), (0.0, 0.0), (10.0, 0.0)), 5.0);
+        assert_eq!(distance_to_line((3.0, 4.0), (0.0, 0.0), (0.0, 0.0)), 5.0);
+    }
+
+    #[test]
+    fn straight_curve_is_one_line() {
+        let mut points = vec![(0.0, 0.0)];
+        flatten_cubic([(0.0, 0.0), (1.0, 0.0), (2.0, 0.0), (3.0, 0.0)], 10, &mut points);
+        assert_eq!(points, vec![(0.0, 0.0), (3.0, 0.0)]);
+    }
+
+    #[test]
+    fn curve_stays_near_its_points() {
+        let mut points = vec![(0.0, 0.0)];
+        flatten_cubic([(0.0, 0.0), (0.0, 20.0), (20.0, 20.0), (20.0, 0.0)], 10, &mut points);
+        assert!(points.len() > 2);
+        assert_eq!(*points.last().unwrap(), (20.0, 0.0));
+        assert!(points.iter().all(|p| p.1 >= 0.0 && p.1 <= 15.0 + TOLERANCE));
+    }
+}
@@ -0,0 +1,210 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! Rasterization helpers that turn shapes into the pixels that need to be plotted.
+//! These work in pixel coordinates, after the layer's transform has been applied.
+
+use std::cmp::Ordering;
+use crate::{FillRule, FPoint};
+
+/// A horizontal run of pixels on row `y`, from `x0` up to (but not including) `x1`
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Span {
+    pub y: u16,
+    pub x0: u16,
+    pub x1: u16,
+}
+
+/// Finds the pixels whose centers are inside of a shape made of one or more
+/// polygons, using the given fill rule. Only pixels inside of a `width` by `height`
+/// area are returned, so the shape doesn't need to be in bounds.
+pub(crate) fn fill_spans<P: AsRef<[FPoint]>>(contours: &[P], rule: FillRule, width: u16, height: u16) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let points = contours.iter().flat_map(|polygon| polygon.as_ref().iter());
+    let min_y = points.clone().map(|p| p.1).fold(f32::INFINITY, f32::min);
+    let max_y = points.map(|p| p.1).fold(f32::NEG_INFINITY, f32::max);
+    // A row is filled based on its center, so these are the rows whose centers
+    // are between min_y and max_y
+    let start = (min_y - 0.5).ceil().max(0.0) as u16;
+    let end = (max_y - 0.5).ceil().min(height as f32).max(0.0) as u16;
+    let mut crossings = Vec::new();
+    for y in start..end {
+        let center = y as f32 + 0.5;
+        crossings.clear();
+        for polygon in contours {
+            let polygon = polygon.as_ref();
+            for (i, &a) in polygon.iter().enumerate() {
+                let b = polygon[(i + 1) % polygon.len()];
+                // Including one end of the edge and not the other keeps vertices from
+                // being counted twice
+                if (a.1 <= center) != (b.1 <= center) {
+                    let x = a.0 + (center - a.1) * (b.0 - a.0) / (b.1 - a.1);
+                    crossings.push((x, if b.1 > a.1 { 1 } else { -1 }));
+                }
+            }
+        }
+        crossings.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        let mut winding = 0;
+        for (i, &(x, direction)) in crossings.iter().enumerate() {
+            winding += direction;
+            if rule.fills(winding) {
+                if let Some(&(next, _)) = crossings.get(i + 1) {
+                    push_span(&mut spans, y, x, next, width);
+                }
+            }
+        }
+    }
+    spans
+}
+
+/// Adds the pixels on row `y` whose centers are between `x0` and `x1`
+fn push_span(spans: &mut Vec<Span>, y: u16, x0: f32, x1: f32, width: u16) {
+    let x0 = (x0 - 0.5).ceil().max(0.0).min(width as f32) as u16;
+    let x1 = (x1 - 0.5).ceil().max(0.0).min(width as f32) as u16;
+    if x0 < x1 {
+        spans.push(Span { y, x0, x1 });
+    }
+}
+
+/// Gets the pixels on the line from `p0` to `p1` using Bresenham's line algorithm.
+/// Every pixel is inside of the rectangle with `p0` and `p1` as its corners.
+pub(crate) fn bresenham(p0: (i32, i32), p1: (i32, i32)) -> Vec<(i32, i32)> {
+    let (mut x, mut y) = p0;
+    let dx = (p1.0 - x).abs();
+    let dy = -(p1.1 - y).abs();
+    let sx = if x < p1.0 { 1 } else { -1 };
+    let sy = if y < p1.1 { 1 } else { -1 };
+    let mut err = dx + dy;
+    let mut points = Vec::with_capacity(dx.max(-dy) as usize + 1);
+    loop {
+        points.push((x, y));
+        if (x, y) == p1 {
+            return points;
+        }
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
+        }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+    }
+}
+
+/// Gets the corners of the rectangle covered by a line from `p0` to `p1` that is
+/// `width` pixels wide. If the points are the same, this is a square around them.
+pub(crate) fn thick_line_quad(p0: FPoint, p1: FPoint, width: f32) -> [FPoint; 4] {
+    let (dx, dy) = (p1.0 - p0.0, p1.1 - p0.1);
+    let len = dx.hypot(dy);
+    let half = width / 2.0;
+    let (nx, ny) = if len == 0.0 { (half, 0.0) } else { (-dy / len * half, dx / len * half) };
+    // Without a direction, stretch the square along the y axis instead
+    let (tx, ty) = if len == 0.0 { (0.0, half) } else { (0.0, 0.0) };
+    [
+        (p0.0 + nx - tx, p0.1 + ny - ty),
+        (p1.0 + nx + tx, p1.1 + ny + ty),
+        (p1.0 - nx + tx, p1.1 - ny + ty),
+        (p0.0 - nx - tx, p0.1 - ny - ty),
+    ]
+}
+
+/// Gets the pixels on the line from `p0` to `p1`, and how much of each is covered
+/// by the line, using Xiaolin Wu's line algorithm
+pub(crate) fn wu_line(p0: FPoint, p1: FPoint) -> Vec<((i32, i32), f32)> {
+    // Wu's algorithm puts pixel centers on whole numbers, while they're at
+    // x.5 everywhere else
+    let (mut x0, mut y0, mut x1, mut y1) = (p0.0 - 0.5, p0.1 - 0.5, p1.0 - 0.5, p1.1 - 0.5);
+    let steep = (y1 - y0).abs() > (x1 - x0).abs();
+    if steep {
+        std::mem::swap(&mut x0, &mut y0);
+        std::mem::swap(&mut x1, &mut y1);
+    }
+    if x0 > x1 {
+        std::mem::swap(&mut x0, &mut x1);
+        std::mem::swap(&mut y0, &mut y1);
+    }
+    let gradient = if x1 == x0 { 1.0 } else { (y1 - y0) / (x1 - x0) };
+    let fract = |n: f32| n - n.floor();
+
+    let mut points = Vec::new();
+    let mut push = |x: i32, y: i32, coverage: f32| {
+        points.push((if steep { (y, x) } else { (x, y) }, coverage));
+    };
+
+    let x_end = x0.round();
+    let y_end = y0 + gradient * (x_end - x0);
+    let gap = 1.0 - fract(x0 + 0.5);
+    let x_start = x_end as i32;
+    push(x_start, y_end.floor() as i32, (1.0 - fract(y_end)) * gap);
+    push(x_start, y_end.floor() as i32 + 1, fract(y_end) * gap);
+    let mut y = y_end + gradient;
+
+    let x_end = x1.round();
+    let y_end = y1 + gradient * (x_end - x1);
+    let gap = fract(x1 + 0.5);
+    let x_stop = x_end as i32;
+    if x_stop != x_start {
+        push(x_stop, y_end.floor() as i32, (1.0 - fract(y_end)) * gap);
+        push(x_stop, y_end.floor() as i32 + 1, fract(y_end) * gap);
+    }
+
+    for x in (x_start + 1)..x_stop {
+        push(x, y.floor() as i32, 1.0 - fract(y));
+        push(x, y.floor() as i32 + 1, fract(y));
+        y += gradient;
+    }
+    points
+}
+
+/// Gets the winding number of a polygon around a point
+pub(crate) fn winding(polygon: &[FPoint], p: FPoint) -> i32 {
+    let mut winding = 0;
+    for (i, &a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        if (a.1 <= p.1) != (b.1 <= p.1) {
+            let x = a.0 + (p.1 - a.1) * (b.0 - a.0) / (b.1 - a.1);
+            if x > p.0 {
+                winding += if b.1 > a.1 { 1 } else { -1 };
+            }
+        }
+    }
+    winding
+}
+
+/// The number of samples taken along each axis of a pixel to estimate coverage
+const SAMPLES: u16 = 4;
+
+/// Estimates how much of a pixel is covered by a shape by sampling a grid of
+/// points inside of it
+pub(crate) fn coverage(p: (i32, i32), inside: impl Fn(FPoint) -> bool) -> f32 {
+    let mut hits = 0;
+    for sy in 0..SAMPLES {
+        for sx in 0..SAMPLES {
+            let sample = (
+                p.0 as f32 + (sx as f32 + 0.5) / SAMPLES as f32,
+                p.1 as f32 + (sy as f32 + 0.5) / SAMPLES as f32,
+            );
+            if inside(sample) {
+                hits += 1;
+            }
+        }
+    }
+    hits as f32 / (SAMPLES * SAMPLES) as f32
+}
+
+/// Gets the pixels, as `(left, top, right, bottom)`, that are touched by a box
+/// around the given points, grown by `margin` and limited to a `width` by `height` area
+pub(crate) fn bounds(points: &[FPoint], margin: f32, width: u16, height: u16) -> (i32, i32, i32, i32) {
+    let left = points.iter().map(|p| p.0).fold(f32::INFINITY, f32::min) - margin;
+    let top = points.iter().map(|p| p.1).fold(f32::INFINITY, f32::min) - margin;
+    let right = points.iter().map(|p| p.0).fold(f32::NEG_INFINITY, f32::max) + margin;
+    let bottom = points.iter().map(|p| p.1).fold(f32::NEG_INFINITY, f32::max) + margin;
+    (
+        left.floor().max(0.0) as i32,
+        top.floor().max(0.0) as i32,
+        right.ceil().min(width as f32) as i32,
+        bottom.ceil().min(height as f32) as i32,
+    )
+}
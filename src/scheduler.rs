@@ -0,0 +1,176 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! The frame scheduler behind `Canvas::request_animation_frame`. Like in a browser,
+//! callbacks are called once, at the start of the next frame, and they all get the
+//! same time, so that everything drawn in a frame keeps time together.
+
+use std::{
+    mem,
+    panic::{self, AssertUnwindSafe},
+    sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+/// How long a frame is, which makes about 60 frames a second
+pub(crate) const FRAME_INTERVAL: Duration = Duration::from_micros(16_667);
+
+type Callback = Box<dyn FnOnce(Instant) + Send>;
+
+struct Queue {
+    /// The callbacks for the next frame, and their ids
+    callbacks: Vec<(i64, Callback)>,
+    next_id: i64,
+    stopped: bool,
+}
+
+/// Runs the callbacks requested for each frame on a thread of its own
+pub(crate) struct FrameScheduler {
+    queue: Mutex<Queue>,
+    /// Woken when a callback is requested or the scheduler is stopped, so that the
+    /// thread doesn't wake up every frame when there's nothing to do
+    wake: Condvar,
+}
+
+impl FrameScheduler {
+    pub fn new() -> Self {
+        Self {
+            queue: Mutex::new(Queue { callbacks: Vec::new(), next_id: 1, stopped: false }),
+            wake: Condvar::new(),
+        }
+    }
+
+    /// Starts the thread that runs the frames, which runs until `stop` is called
+    pub fn spawn(self: &Arc<Self>) -> JoinHandle<()> {
+        let scheduler = Arc::clone(self);
+        thread::spawn(move || scheduler.run())
+    }
+
+    /// Adds a callback to the next frame, and returns its id
+    pub fn request(&self, f: Callback) -> i64 {
+        let mut queue = self.lock();
+        let id = queue.next_id;
+        queue.next_id += 1;
+        queue.callbacks.push((id, f));
+        self.wake.notify_one();
+        id
+    }
+
+    /// Removes a callback that hasn't been called yet
+    pub fn cancel(&self, id: i64) {
+        self.lock().callbacks.retain(|(other, _)| *other != id);
+    }
+
+    /// Stops the thread, after the frame it's running (if any). Callbacks that
+    /// haven't been called are dropped.
+    pub fn stop(&self) {
+        let mut queue = self.lock();
+        queue.stopped = true;
+        queue.callbacks.clear();
+        self.wake.notify_one();
+    }
+
+    /// Calls every callback that was requested before the frame started. Callbacks
+    /// requested by these callbacks are left for the next frame.
+    pub fn run_frame(&self, now: Instant) {
+        let callbacks = mem::take(&mut self.lock().callbacks);
+        for (_, f) in callbacks {
+            // A callback that panics shouldn't stop every other callback from running
+            let _ = panic::catch_unwind(AssertUnwindSafe(|| f(now)));
+        }
+    }
+
+    fn run(&self) {
+        let mut next_frame = Instant::now();
+        loop {
+            {
+                let mut queue = self.lock();
+                while queue.callbacks.is_empty() && !queue.stopped {
+                    queue = self.wake.wait(queue).unwrap_or_else(PoisonError::into_inner);
+                }
+                if queue.stopped {
+                    return;
+                }
+            }
+            let now = Instant::now();
+            if now < next_frame {
+                thread::sleep(next_frame - now);
+            }
+            let now = Instant::now();
+            // Frames that were missed aren't made up for
+            next_frame = (next_frame + FRAME_INTERVAL).max(now);
+            self.run_frame(now);
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::channel;
+
+    #[test]
+    fn callbacks_run_once() {
+        let scheduler = FrameScheduler::new();
+        let (sender, receiver) = channel();
+        let now = Instant::now();
+        scheduler.request(Box::new(move |time| sender.send(time).unwrap()));
+        scheduler.run_frame(now);
+        scheduler.run_frame(now + FRAME_INTERVAL);
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), [now]);
+    }
+
+    #[test]
+    fn cancelled_callbacks_dont_run() {
+        let scheduler = FrameScheduler::new();
+        let (sender, receiver) = channel();
+        let first = scheduler.request(Box::new(|_| panic!("cancelled")));
+        let second = scheduler.request(Box::new(move |_| sender.send(()).unwrap()));
+        assert!(second > first);
+        scheduler.cancel(first);
+        scheduler.run_frame(Instant::now());
+        assert_eq!(receiver.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn callbacks_requested_in_a_frame_wait_for_the_next() {
+        let scheduler = Arc::new(FrameScheduler::new());
+        let (sender, receiver) = channel();
+        let inner = Arc::clone(&scheduler);
+        scheduler.request(Box::new(move |_| {
+            inner.request(Box::new(move |_| sender.send(()).unwrap()));
+        }));
+        scheduler.run_frame(Instant::now());
+        assert_eq!(receiver.try_iter().count(), 0);
+        scheduler.run_frame(Instant::now());
+        assert_eq!(receiver.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn panicking_callback_doesnt_stop_others() {
+        let scheduler = FrameScheduler::new();
+        let (sender, receiver) = channel();
+        scheduler.request(Box::new(|_| panic!("callback failed")));
+        scheduler.request(Box::new(move |_| sender.send(()).unwrap()));
+        scheduler.run_frame(Instant::now());
+        assert_eq!(receiver.try_iter().count(), 1);
+    }
+
+    #[test]
+    fn thread_runs_frames_until_stopped() {
+        let scheduler = Arc::new(FrameScheduler::new());
+        let thread = scheduler.spawn();
+        let (sender, receiver) = channel();
+        let start = Instant::now();
+        scheduler.request(Box::new(move |time| sender.send(time).unwrap()));
+        let time = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(time >= start);
+        scheduler.stop();
+        thread.join().unwrap();
+    }
+}
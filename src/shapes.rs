@@ -0,0 +1,292 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! Polygons, circles, ellipses, arcs and pie slices
+
+use std::f32::consts::PI;
+use crate::{error::ErrorKind, paint::{IntoPaint, Paint, Shader}, Color, FillRule, FPoint, Layer, Result};
+
+impl<'a> Layer<'a> {
+    /// Draws and fills a polygon. Concave and self-intersecting polygons are
+    /// filled according to `rule`. If `paint` is `None`, the current fill paint is used.
+    pub fn fill_polygon(&mut self, points: &[FPoint], rule: FillRule, paint: impl IntoPaint) -> Result<()> {
+        let paint = self.fill_paint(paint);
+        let points: Vec<FPoint> = points.iter().map(|&p| self.state.transform.apply(p)).collect();
+        self.validate_f_points(&points)?;
+        self.fill_polygon_px(&points, rule, &paint)
+    }
+
+    /// Draws the outline of a polygon, connecting the last point back to the first.
+    /// If `color` is `None`, the current stroke color is used.
+    pub fn stroke_polygon(&mut self, points: &[FPoint], color: impl Into<Option<Color>>) -> Result<()> {
+        let color = color.into().unwrap_or(self.state.stroke_color);
+        let points: Vec<FPoint> = points.iter().map(|&p| self.state.transform.apply(p)).collect();
+        self.validate_f_points(&points)?;
+        self.stroke_px(vec![(points, true)], color)
+    }
+
+    /// Draws the outline of a circle, using the current line width. If `color`
+    /// is `None`, the current stroke color is used.
+    pub fn stroke_circle(&mut self, center: FPoint, radius: f32, color: impl Into<Option<Color>>) -> Result<()> {
+        self.stroke_ellipse(center, radius, radius, color)
+    }
+
+    /// Draws and fills a circle. If `paint` is `None`, the current fill paint is used.
+    pub fn fill_circle(&mut self, center: FPoint, radius: f32, paint: impl IntoPaint) -> Result<()> {
+        self.fill_ellipse(center, radius, radius, paint)
+    }
+
+    /// Draws the outline of an ellipse with the horizontal radius `rx` and the
+    /// vertical radius `ry`, using the current line width. If `color` is `None`,
+    /// the current stroke color is used.
+    pub fn stroke_ellipse(&mut self, center: FPoint, rx: f32, ry: f32, color: impl Into<Option<Color>>) -> Result<()> {
+        let color = color.into().unwrap_or(self.state.stroke_color);
+        self.ellipse(center, (rx, ry), None, false, &Paint::Solid(color))
+    }
+
+    /// Draws and fills an ellipse with the horizontal radius `rx` and the vertical
+    /// radius `ry`. If `paint` is `None`, the current fill paint is used.
+    pub fn fill_ellipse(&mut self, center: FPoint, rx: f32, ry: f32, paint: impl IntoPaint) -> Result<()> {
+        let paint = self.fill_paint(paint);
+        self.ellipse(center, (rx, ry), None, true, &paint)
+    }
+
+    /// Draws part of the outline of a circle, going clockwise from `start` to
+    /// `end`. Angles are in radians, and 0 points to the right. If `color` is
+    /// `None`, the current stroke color is used.
+    pub fn arc(&mut self, center: FPoint, radius: f32, start: f32, end: f32, color: impl Into<Option<Color>>) -> Result<()> {
+        let color = color.into().unwrap_or(self.state.stroke_color);
+        self.ellipse(center, (radius, radius), Some((start, end)), false, &Paint::Solid(color))
+    }
+
+    /// Draws and fills a slice of a circle, going clockwise from `start` to `end`.
+    /// Angles are in radians, and 0 points to the right. If `paint` is `None`, the
+    /// current fill paint is used.
+    pub fn pie(&mut self, center: FPoint, radius: f32, start: f32, end: f32, paint: impl IntoPaint) -> Result<()> {
+        let paint = self.fill_paint(paint);
+        self.ellipse(center, (radius, radius), Some((start, end)), true, &paint)
+    }
+
+    /// Draws an ellipse, or the part of it between two angles, using the midpoint
+    /// algorithm. The transform moves the center and scales the radii, but it doesn't
+    /// skew the ellipse.
+    fn ellipse(&mut self, center: FPoint, radii: (f32, f32), angles: Option<(f32, f32)>, fill: bool, paint: &Paint) -> Result<()> {
+        let transform = self.state.transform;
+        let shader = Shader::new(paint, &transform);
+        let center = transform.apply(center);
+        let rx = radii.0 * transform.a.hypot(transform.b);
+        let ry = radii.1 * transform.c.hypot(transform.d);
+        let angles = angles.map(|(start, end)| {
+            let rotation = transform.b.atan2(transform.a);
+            (start + rotation, end + rotation)
+        });
+        let half_width = if fill || self.state.line_width <= 1.0 { 0.0 } else { self.state.line_width / 2.0 };
+
+        let in_angles = |x: i32, y: i32| match angles {
+            Some((start, end)) => {
+                let angle = (y as f32 + 0.5 - center.1).atan2(x as f32 + 0.5 - center.0);
+                end - start >= 2.0 * PI || (angle - start).rem_euclid(2.0 * PI) <= (end - start).rem_euclid(2.0 * PI)
+            },
+            None => true,
+        };
+        let outer = EllipseSpans::new(center, (rx + half_width, ry + half_width));
+        let inner = EllipseSpans::new(center, (rx - half_width, ry - half_width));
+        // The pixels of a row that are inside of the ellipse, but aren't on the outline
+        let hole = |y: i32, (left, right): (i32, i32)| {
+            if fill {
+                None
+            }
+            else if half_width == 0.0 {
+                let above = outer.get(y - 1)?;
+                let below = outer.get(y + 1)?;
+                Some(((left + 1).max(above.0).max(below.0), (right - 1).min(above.1).min(below.1)))
+            }
+            else {
+                inner.get(y)
+            }
+        };
+
+        let (left, top, right, bottom) = match outer.bounds() {
+            Some(bounds) => bounds,
+            None => return Ok(()),
+        };
+        self.validate_px_box(left, top, right, bottom)?;
+        for y in top..=bottom {
+            let (left, right) = match outer.get(y) {
+                Some(span) => span,
+                None => continue,
+            };
+            let (hole_left, hole_right) = match hole(y, (left, right)) {
+                Some((hole_left, hole_right)) if hole_left <= hole_right => (hole_left, hole_right),
+                _ => (right + 1, right),
+            };
+            for x in (left..hole_left).chain(hole_right + 1..=right) {
+                if in_angles(x, y) {
+                    self.plot_shaded((x as u16, y as u16), &shader)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks that every pixel from `(left, top)` to `(right, bottom)`, inclusive,
+    /// is in bounds
+    fn validate_px_box(&self, left: i32, top: i32, right: i32, bottom: i32) -> Result<()> {
+        if left < 0 || top < 0 {
+            return Err(ErrorKind::InvalidPoint(left as f32, top as f32));
+        }
+        if right > u16::MAX as i32 || bottom > u16::MAX as i32 {
+            return Err(ErrorKind::InvalidPoint(right as f32, bottom as f32));
+        }
+        self.validate_i_points(&[(left as u16, top as u16), (right as u16, bottom as u16)])
+    }
+}
+
+/// The first and last pixel of each row of an ellipse, from the top down
+struct EllipseSpans {
+    top: i32,
+    spans: Vec<(i32, i32)>,
+}
+
+impl EllipseSpans {
+    /// Finds the rows of an ellipse with the midpoint algorithm. The center is moved
+    /// to the nearest half pixel, so that the ellipse is symmetric on the pixel grid.
+    fn new(center: FPoint, radii: (f32, f32)) -> Self {
+        let empty = Self { top: 0, spans: Vec::new() };
+        if !(radii.0 > 0.0 && radii.1 > 0.0) {
+            return empty;
+        }
+        // Everything is doubled, so that the distances from the center to the
+        // pixel centers are whole numbers. They are odd when the center is on the
+        // edge of a pixel, and even when it is in the middle of one.
+        let (cx, cy) = ((center.0 * 2.0).round() as i32, (center.1 * 2.0).round() as i32);
+        let (a, b) = (radii.0 * 2.0, radii.1 * 2.0);
+        let (first_u, first_v) = (1 - cx.rem_euclid(2), 1 - cy.rem_euclid(2));
+        if first_u as f32 > a {
+            return empty;
+        }
+        let outside = |u: i32, v: i32| {
+            let (u, v) = (u as f32, v as f32);
+            b * b * u * u + a * a * v * v > a * a * b * b
+        };
+
+        // The widest point of each row of the bottom right quarter, going up from the middle
+        let mut widths = Vec::new();
+        let mut record = |u: i32, v: i32| {
+            let row = ((v - first_v) / 2) as usize;
+            if row >= widths.len() {
+                widths.resize(row + 1, u);
+            }
+            widths[row] = widths[row].max(u);
+        };
+        let top_v = b * (1.0 - (first_u as f32 / a).powi(2)).sqrt();
+        let (mut u, mut v) = (first_u, first_v + 2 * ((top_v - first_v as f32) / 2.0).round() as i32);
+        // Where the curve is flatter than 45°, it moves across one pixel at a time
+        while v >= first_v && b * b * (u as f32) < a * a * (v as f32) {
+            record(u, v);
+            u += 2;
+            if outside(u, v - 1) {
+                v -= 2;
+            }
+        }
+        // Where it is steeper, it moves down one pixel at a time
+        while v >= first_v {
+            record(u, v);
+            v -= 2;
+            if !outside(u + 1, v) {
+                u += 2;
+            }
+        }
+        if widths.is_empty() {
+            return empty;
+        }
+
+        let row_v = |row: usize| first_v + 2 * row as i32;
+        let top_row = widths.len() - 1;
+        // The middle row is only counted once if the center is in the middle of a pixel
+        let spans = (0..=top_row).rev()
+            .chain((0..=top_row).skip(if first_v == 0 { 1 } else { 0 }))
+            .map(|row| ((cx - widths[row] - 1) / 2, (cx + widths[row] - 1) / 2))
+            .collect();
+        Self { top: (cy - row_v(top_row) - 1) / 2, spans }
+    }
+
+    /// Gets the first and last pixels of row `y`
+    fn get(&self, y: i32) -> Option<(i32, i32)> {
+        let row = y.checked_sub(self.top)?;
+        if row < 0 {
+            return None;
+        }
+        self.spans.get(row as usize).copied()
+    }
+
+    /// Gets the leftmost, top, rightmost and bottom pixels of the ellipse
+    fn bounds(&self) -> Option<(i32, i32, i32, i32)> {
+        let left = self.spans.iter().map(|span| span.0).min()?;
+        let right = self.spans.iter().map(|span| span.1).max()?;
+        Some((left, self.top, right, self.top + self.spans.len() as i32 - 1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(center: FPoint, radii: (f32, f32)) -> Vec<(i32, i32, i32)> {
+        let spans = EllipseSpans::new(center, radii);
+        spans.spans.iter().enumerate().map(|(row, &(left, right))| (spans.top + row as i32, left, right)).collect()
+    }
+
+    #[test]
+    fn circle_matches_midpoint_circle() {
+        // The rows of the usual midpoint circle with a radius of 3
+        assert_eq!(spans((10.5, 10.5), (3.0, 3.0)), [
+            (7, 9, 11), (8, 8, 12), (9, 7, 13), (10, 7, 13), (11, 7, 13), (12, 8, 12), (13, 9, 11),
+        ]);
+    }
+
+    #[test]
+    fn spans_are_symmetric() {
+        for &(center, radii) in [((10.0, 8.0), (6.5, 4.2)), ((10.5, 8.0), (3.0, 5.0)), ((10.3, 7.8), (7.0, 1.5))].iter() {
+            let spans = spans(center, radii);
+            let (cx, cy) = ((center.0 * 2.0).round() as i32, (center.1 * 2.0).round() as i32);
+            for (&(y, left, right), &(other_y, ..)) in spans.iter().zip(spans.iter().rev()) {
+                assert_eq!(left + right + 1, cx, "row {}", y);
+                assert_eq!(y + other_y + 1, cy, "row {}", y);
+            }
+            assert!(spans.windows(2).all(|pair| pair[1].0 == pair[0].0 + 1));
+        }
+    }
+
+    #[test]
+    fn spans_stay_near_curve() {
+        let (center, radii) = ((20.0, 20.0), (12.0, 7.0));
+        // The end of each row is within a pixel of the curve, either across or down
+        let curve = |d: f32, r: f32, other_r: f32| other_r * (1.0 - (d / r).powi(2).min(1.0)).sqrt();
+        for (y, left, right) in spans(center, radii) {
+            let (dx, dy) = (right as f32 + 0.5 - center.0, (y as f32 + 0.5 - center.1).abs());
+            let across = (dx - curve(dy, radii.1, radii.0)).abs();
+            let down = (dy - curve(dx, radii.0, radii.1)).abs();
+            assert!(across <= 1.0 || down <= 1.0, "row {}", y);
+            assert_eq!(left + right + 1, 40);
+        }
+    }
+
+    #[test]
+    fn empty_ellipse_has_no_spans() {
+        let spans = EllipseSpans::new((5.0, 5.0), (0.0, 3.0));
+        assert_eq!(spans.bounds(), None);
+        assert_eq!(spans.get(5), None);
+        assert_eq!(EllipseSpans::new((5.0, 5.0), (-1.0, 3.0)).bounds(), None);
+        assert_eq!(EllipseSpans::new((5.0, 5.0), (0.2, 3.0)).bounds(), None);
+    }
+
+    #[test]
+    fn bounds_cover_every_row() {
+        let spans = EllipseSpans::new((5.5, 5.5), (2.0, 4.0));
+        assert_eq!(spans.bounds(), Some((3, 1, 7, 9)));
+        assert_eq!(spans.get(0), None);
+        assert_eq!(spans.get(10), None);
+        assert_eq!(spans.get(5), Some((3, 7)));
+    }
+}
@@ -0,0 +1,285 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module holds sprite sheets, which are images with many smaller images
+//! (frames) in them, so that they only need to be decoded once.
+
+use std::{collections::HashMap, io::Read, sync::Arc};
+#[cfg(feature = "atlas")]
+use serde::Deserialize;
+use crate::{bitmap::RgbaImage, error::ErrorKind, IPoint, Layer, Rect, Result};
+
+/// Which ways a sprite is flipped when it is drawn
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Flip {
+    /// Not flipped
+    None,
+
+    /// Flipped left to right
+    Horizontal,
+
+    /// Flipped top to bottom
+    Vertical,
+
+    /// Flipped both ways, which is the same as turning it halfway around
+    Both,
+}
+
+/// An image split into named frames, used by `Layer::draw_sprite`. The image is
+/// only decoded once, and cloning a sprite sheet doesn't copy it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpriteSheet {
+    image: Arc<RgbaImage>,
+    frames: HashMap<String, Rect>,
+}
+
+impl SpriteSheet {
+    /// Creates a sprite sheet from an image, without any frames
+    pub fn new(image: RgbaImage) -> Self {
+        Self { image: Arc::new(image), frames: HashMap::new() }
+    }
+
+    /// Decodes the image of a sprite sheet, in any format that `Layer::draw_img` can
+    /// draw, without any frames
+    pub fn from_reader(src: &mut impl Read) -> Result<Self> {
+        Ok(Self::new(RgbaImage::from_reader(src)?))
+    }
+
+    /// The image the frames are in
+    pub fn image(&self) -> &RgbaImage {
+        &self.image
+    }
+
+    /// Gets the rectangle of the image that a frame is
+    pub fn frame(&self, name: &str) -> Option<Rect> {
+        self.frames.get(name).copied()
+    }
+
+    /// Returns an iterator over the names of the frames, in no particular order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.frames.keys().map(String::as_str)
+    }
+
+    /// Adds a frame, replacing the frame with the same name if there is one
+    pub fn add_frame(&mut self, name: impl Into<String>, rect: Rect) -> Result<()> {
+        let name = name.into();
+        if rect.x as u32 + rect.width as u32 > self.image.width() || rect.y as u32 + rect.height as u32 > self.image.height() {
+            return Err(ErrorKind::SpriteError(format!("frame \"{}\" goes past the edge of the image", name)));
+        }
+        self.frames.insert(name, rect);
+        Ok(())
+    }
+
+    /// Splits the whole image into frames that are `width` by `height` pixels. They
+    /// are named `prefix` followed by a number, counting from 0 left to right and
+    /// then top to bottom. Pixels left over at the right and bottom are left out, and
+    /// so are pixels past 65535, since frames can't start that far out.
+    pub fn add_grid(&mut self, prefix: &str, width: u16, height: u16) -> Result<()> {
+        if width == 0 || height == 0 {
+            return Err(ErrorKind::SpriteError("grid cells can't be empty".to_string()));
+        }
+        let columns = self.image.width().min(u16::MAX as u32) / width as u32;
+        let rows = self.image.height().min(u16::MAX as u32) / height as u32;
+        for row in 0..rows {
+            for column in 0..columns {
+                let rect = Rect::new(column as u16 * width, row as u16 * height, width, height);
+                self.add_frame(format!("{}{}", prefix, row * columns + column), rect)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds the frames listed in a JSON descriptor. `"frames"` is either an object
+    /// of names and rectangles, or a list of objects with a `"filename"` and a
+    /// rectangle in `"frame"`, like the ones made by TexturePacker. Rectangles are
+    /// objects with `"x"`, `"y"`, `"w"` and `"h"`.
+    ///
+    /// ```json
+    /// { "frames": { "walk_0": { "x": 0, "y": 0, "w": 16, "h": 16 } } }
+    /// ```
+    #[cfg(feature = "atlas")]
+    pub fn add_json(&mut self, json: &str) -> Result<()> {
+        let descriptor = serde_json::from_str(json).map_err(|err| ErrorKind::SpriteError(err.to_string()))?;
+        self.add_descriptor(descriptor)
+    }
+
+    /// Adds the frames listed in a TOML descriptor, which has the same layout as the
+    /// JSON ones read by `add_json`
+    ///
+    /// ```toml
+    /// [frames.walk_0]
+    /// x = 0
+    /// y = 0
+    /// w = 16
+    /// h = 16
+    /// ```
+    #[cfg(feature = "atlas")]
+    pub fn add_toml(&mut self, toml: &str) -> Result<()> {
+        let descriptor = toml::from_str(toml).map_err(|err| ErrorKind::SpriteError(err.to_string()))?;
+        self.add_descriptor(descriptor)
+    }
+
+    #[cfg(feature = "atlas")]
+    fn add_descriptor(&mut self, descriptor: Descriptor) -> Result<()> {
+        match descriptor.frames {
+            Frames::Map(frames) => {
+                for (name, frame) in frames {
+                    let rect = match frame {
+                        FrameEntry::Rect(rect) => rect,
+                        FrameEntry::Packed { frame } => frame,
+                    };
+                    self.add_frame(name, rect.into())?;
+                }
+            },
+            Frames::List(frames) => {
+                for frame in frames {
+                    self.add_frame(frame.filename, frame.frame.into())?;
+                }
+            },
+        }
+        Ok(())
+    }
+}
+
+/// The layout of JSON and TOML sprite sheet descriptors
+#[cfg(feature = "atlas")]
+#[derive(Deserialize)]
+struct Descriptor {
+    frames: Frames,
+}
+
+#[cfg(feature = "atlas")]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Frames {
+    Map(HashMap<String, FrameEntry>),
+    List(Vec<NamedFrame>),
+}
+
+#[cfg(feature = "atlas")]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FrameEntry {
+    Rect(FrameRect),
+    Packed { frame: FrameRect },
+}
+
+#[cfg(feature = "atlas")]
+#[derive(Deserialize)]
+struct NamedFrame {
+    filename: String,
+    frame: FrameRect,
+}
+
+#[cfg(feature = "atlas")]
+#[derive(Deserialize)]
+struct FrameRect {
+    x: u16,
+    y: u16,
+    w: u16,
+    h: u16,
+}
+
+#[cfg(feature = "atlas")]
+impl From<FrameRect> for Rect {
+    fn from(rect: FrameRect) -> Self {
+        Rect::new(rect.x, rect.y, rect.w, rect.h)
+    }
+}
+
+impl<'a> Layer<'a> {
+    /// Draws a frame of a sprite sheet with its top left corner at `p`. Pixels are
+    /// drawn the same way as in `draw_img`, so transparent pixels and pixels that are
+    /// the color key are left out.
+    pub fn draw_sprite(&mut self, sheet: &SpriteSheet, name: &str, p: IPoint, flip: Flip) -> Result<()> {
+        let rect = sheet.frame(name)
+            .ok_or_else(|| ErrorKind::SpriteError(format!("there is no frame named \"{}\"", name)))?;
+        let (flip_x, flip_y) = match flip {
+            Flip::None => (false, false),
+            Flip::Horizontal => (true, false),
+            Flip::Vertical => (false, true),
+            Flip::Both => (true, true),
+        };
+        self.validate_image(p.0, p.1, rect.width as u32, rect.height as u32)?;
+        for y in 0..rect.height {
+            for x in 0..rect.width {
+                let src_x = if flip_x { rect.width - 1 - x } else { x };
+                let src_y = if flip_y { rect.height - 1 - y } else { y };
+                let rgba = sheet.image.get_pixel((rect.x + src_x) as u32, (rect.y + src_y) as u32);
+                self.draw_img_px((p.0 as i32 + x as i32, p.1 as i32 + y as i32), rgba)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sheet(width: u32, height: u32) -> SpriteSheet {
+        SpriteSheet::new(RgbaImage::new(width, height))
+    }
+
+    fn sprite_error(result: Result<()>) -> bool {
+        matches!(result, Err(ErrorKind::SpriteError(_)))
+    }
+
+    #[test]
+    fn frames_must_be_in_image() {
+        let mut sheet = sheet(16, 8);
+        sheet.add_frame("all", Rect::new(0, 0, 16, 8)).unwrap();
+        assert!(sprite_error(sheet.add_frame("wide", Rect::new(1, 0, 16, 8))));
+        assert!(sprite_error(sheet.add_frame("far", Rect::new(u16::MAX, u16::MAX, u16::MAX, 1))));
+        assert_eq!(sheet.frame("all"), Some(Rect::new(0, 0, 16, 8)));
+        assert_eq!(sheet.names().collect::<Vec<_>>(), ["all"]);
+    }
+
+    #[test]
+    fn grid_counts_across_then_down() {
+        let mut sheet = sheet(10, 7);
+        sheet.add_grid("walk_", 3, 3).unwrap();
+        let mut names: Vec<_> = sheet.names().collect();
+        names.sort_unstable();
+        assert_eq!(names, ["walk_0", "walk_1", "walk_2", "walk_3", "walk_4", "walk_5"]);
+        assert_eq!(sheet.frame("walk_4"), Some(Rect::new(3, 3, 3, 3)));
+        assert!(sprite_error(sheet.add_grid("empty", 0, 3)));
+    }
+
+    #[test]
+    fn grid_stops_at_largest_position() {
+        let mut sheet = sheet(70_000, 1);
+        sheet.add_grid("", 20_000, 1).unwrap();
+        assert_eq!(sheet.names().count(), 3);
+        assert_eq!(sheet.frame("2"), Some(Rect::new(40_000, 0, 20_000, 1)));
+    }
+
+    #[cfg(feature = "atlas")]
+    #[test]
+    fn json_frames_as_map_or_list() {
+        let mut sheet = sheet(32, 16);
+        sheet.add_json(r#"{ "frames": { "a": { "x": 0, "y": 0, "w": 16, "h": 16 }, "b": { "frame": { "x": 16, "y": 0, "w": 16, "h": 16 } } } }"#).unwrap();
+        sheet.add_json(r#"{ "frames": [{ "filename": "c", "frame": { "x": 8, "y": 8, "w": 8, "h": 8 } }] }"#).unwrap();
+        assert_eq!(sheet.frame("b"), Some(Rect::new(16, 0, 16, 16)));
+        assert_eq!(sheet.frame("c"), Some(Rect::new(8, 8, 8, 8)));
+        assert_eq!(sheet.names().count(), 3);
+    }
+
+    #[cfg(feature = "atlas")]
+    #[test]
+    fn toml_frames() {
+        let mut sheet = sheet(32, 16);
+        sheet.add_toml("[frames.walk_0]\nx = 0\ny = 0\nw = 16\nh = 16\n").unwrap();
+        assert_eq!(sheet.frame("walk_0"), Some(Rect::new(0, 0, 16, 16)));
+    }
+
+    #[cfg(feature = "atlas")]
+    #[test]
+    fn bad_descriptors_are_errors() {
+        let mut sheet = sheet(32, 16);
+        assert!(sprite_error(sheet.add_json("{")));
+        assert!(sprite_error(sheet.add_json(r#"{ "frames": { "a": { "x": -1, "y": 0, "w": 1, "h": 1 } } }"#)));
+        assert!(sprite_error(sheet.add_json(r#"{ "frames": { "a": { "x": 0, "y": 0, "w": 64, "h": 1 } } }"#)));
+        assert!(sprite_error(sheet.add_toml("frames = 3")));
+    }
+}
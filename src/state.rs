@@ -0,0 +1,274 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module holds the drawing state of a layer. It works like the state of an
+//! HTML canvas, so it can be saved with `Layer::save` and brought back with
+//! `Layer::restore`.
+
+use crate::{paint::Paint, Color, FPoint, Rect};
+
+/// A 2D affine transformation, in the same form as the one used by HTML's
+/// `CanvasRenderingContext2D.setTransform(a, b, c, d, e, f)`.
+///
+/// A point `(x, y)` is mapped to `(a * x + c * y + e, b * x + d * y + f)`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    /// Horizontal scaling
+    pub a: f32,
+
+    /// Vertical skewing
+    pub b: f32,
+
+    /// Horizontal skewing
+    pub c: f32,
+
+    /// Vertical scaling
+    pub d: f32,
+
+    /// Horizontal translation
+    pub e: f32,
+
+    /// Vertical translation
+    pub f: f32,
+}
+
+impl Transform {
+    /// The transform that leaves every point where it is
+    pub const IDENTITY: Transform = Transform { a: 1.0, b: 0.0, c: 0.0, d: 1.0, e: 0.0, f: 0.0 };
+
+    /// A transform that moves points by `(x, y)`
+    pub fn translation(x: f32, y: f32) -> Self {
+        Self { e: x, f: y, ..Self::IDENTITY }
+    }
+
+    /// A transform that scales points by `x` horizontally and `y` vertically
+    pub fn scaling(x: f32, y: f32) -> Self {
+        Self { a: x, d: y, ..Self::IDENTITY }
+    }
+
+    /// A transform that rotates points clockwise by `angle` radians (clockwise because the
+    /// y axis points down)
+    pub fn rotation(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        Self { a: cos, b: sin, c: -sin, d: cos, e: 0.0, f: 0.0 }
+    }
+
+    /// Returns a transform that applies `other` first, and then `self`
+    pub fn then(&self, other: &Transform) -> Self {
+        Self {
+            a: self.a * other.a + self.c * other.b,
+            b: self.b * other.a + self.d * other.b,
+            c: self.a * other.c + self.c * other.d,
+            d: self.b * other.c + self.d * other.d,
+            e: self.a * other.e + self.c * other.f + self.e,
+            f: self.b * other.e + self.d * other.f + self.f,
+        }
+    }
+
+    /// Maps a point through this transform
+    pub fn apply(&self, p: FPoint) -> FPoint {
+        (self.a * p.0 + self.c * p.1 + self.e, self.b * p.0 + self.d * p.1 + self.f)
+    }
+
+    /// Gets the transform that undoes this one, if there is one
+    pub fn inverse(&self) -> Option<Self> {
+        let det = self.a * self.d - self.b * self.c;
+        if det == 0.0 || !det.is_finite() {
+            return None;
+        }
+        Some(Self {
+            a: self.d / det,
+            b: -self.b / det,
+            c: -self.c / det,
+            d: self.a / det,
+            e: (self.c * self.f - self.d * self.e) / det,
+            f: (self.b * self.e - self.a * self.f) / det,
+        })
+    }
+
+    /// How much this transform scales lengths by, on average. This is used for
+    /// sizes that aren't points, such as radii.
+    pub fn scale_factor(&self) -> f32 {
+        (self.a * self.d - self.b * self.c).abs().sqrt()
+    }
+
+    /// Whether this transform keeps horizontal lines horizontal and vertical lines vertical
+    pub fn is_axis_aligned(&self) -> bool {
+        self.b == 0.0 && self.c == 0.0
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+/// The shape drawn at the ends of lines and of unclosed paths
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LineCap {
+    /// The line stops exactly at its end point
+    Butt,
+
+    /// The line ends with a half circle around its end point
+    Round,
+
+    /// The line continues past its end point by half of its width
+    Square,
+}
+
+/// The shape drawn where two lines of a path or polygon meet
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum LineJoin {
+    /// The outer edges of the lines are extended until they meet. If that point
+    /// is too far away (see `DrawState::miter_limit`), a bevel join is used instead
+    Miter,
+
+    /// The corner is rounded off with a circle
+    Round,
+
+    /// The outer corners of the lines are connected with a straight line
+    Bevel,
+}
+
+/// An arrowhead drawn at the start or end of a line or unclosed path. The line
+/// is shortened so that it doesn't stick out past the tip.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Arrowhead {
+    /// The distance, in pixels, from the tip to the back of the arrowhead
+    pub length: f32,
+
+    /// The width, in pixels, of the back of the arrowhead
+    pub width: f32,
+}
+
+/// The state used by a layer's drawing functions when they aren't given
+/// everything explicitly
+#[derive(Clone, Debug, PartialEq)]
+pub struct DrawState {
+    /// What shapes are filled with when no paint is given. Defaults to `Color::White`
+    pub fill: Paint,
+
+    /// The color used to draw lines and outlines when no color is given. Defaults to `Color::White`
+    pub stroke_color: Color,
+
+    /// If set, nothing is drawn outside of this rectangle. It is in pixels, and is
+    /// not affected by `transform`
+    pub clip: Option<Rect>,
+
+    /// The transform applied to the points given to drawing functions
+    pub transform: Transform,
+
+    /// The width, in pixels, of lines and outlines. Defaults to `1.0`
+    pub line_width: f32,
+
+    /// The shape of the ends of lines. Defaults to `LineCap::Butt`
+    pub line_cap: LineCap,
+
+    /// The shape of the corners where lines meet. Defaults to `LineJoin::Miter`
+    pub line_join: LineJoin,
+
+    /// How long a miter join can be, compared to the line width, before a bevel
+    /// join is used instead. Defaults to `10.0`
+    pub miter_limit: f32,
+
+    /// The lengths of alternating dashes and gaps, in pixels. If it has an odd
+    /// number of entries, it is repeated to make it even. Lines are solid if this
+    /// is empty, which is the default.
+    pub dash: Vec<f32>,
+
+    /// How far into the dash pattern lines start. Defaults to `0.0`
+    pub dash_offset: f32,
+
+    /// The arrowhead drawn at the start of lines. Defaults to `None`
+    pub start_arrow: Option<Arrowhead>,
+
+    /// The arrowhead drawn at the end of lines. Defaults to `None`
+    pub end_arrow: Option<Arrowhead>,
+
+    /// Pixels of this color in images, such as the ones drawn with `Layer::draw_img`
+    /// and `Layer::draw_sprite`, are left out as if they were transparent. Defaults
+    /// to `None`
+    pub color_key: Option<Color>,
+}
+
+impl DrawState {
+    /// The color shapes are filled with when no paint is given, or `None` if the
+    /// fill paint isn't a single color
+    pub fn fill_color(&self) -> Option<Color> {
+        match self.fill {
+            Paint::Solid(color) => Some(color),
+            _ => None,
+        }
+    }
+
+    /// Makes shapes be filled with a single color when no paint is given
+    pub fn set_fill_color(&mut self, color: Color) {
+        self.fill = Paint::Solid(color);
+    }
+}
+
+impl Default for DrawState {
+    fn default() -> Self {
+        Self {
+            fill: Paint::Solid(Color::White),
+            stroke_color: Color::White,
+            clip: None,
+            transform: Transform::IDENTITY,
+            line_width: 1.0,
+            line_cap: LineCap::Butt,
+            line_join: LineJoin::Miter,
+            miter_limit: 10.0,
+            dash: Vec::new(),
+            dash_offset: 0.0,
+            start_arrow: None,
+            end_arrow: None,
+            color_key: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: FPoint, b: FPoint) -> bool {
+        (a.0 - b.0).abs() < 1e-4 && (a.1 - b.1).abs() < 1e-4
+    }
+
+    #[test]
+    fn then_applies_other_first() {
+        let t = Transform::translation(10.0, 0.0).then(&Transform::scaling(2.0, 3.0));
+        assert!(close(t.apply((1.0, 1.0)), (12.0, 3.0)));
+    }
+
+    #[test]
+    fn rotation_is_clockwise() {
+        let t = Transform::rotation(std::f32::consts::FRAC_PI_2);
+        assert!(close(t.apply((1.0, 0.0)), (0.0, 1.0)));
+        assert!(!t.is_axis_aligned());
+    }
+
+    #[test]
+    fn inverse_undoes_transform() {
+        let t = Transform::translation(-5.0, 7.0).then(&Transform::rotation(0.3)).then(&Transform::scaling(2.0, 0.5));
+        let inverse = t.inverse().unwrap();
+        assert!(close(inverse.apply(t.apply((3.0, -4.0))), (3.0, -4.0)));
+    }
+
+    #[test]
+    fn fill_color_is_only_for_solid_fills() {
+        let mut state = DrawState::default();
+        assert_eq!(state.fill_color(), Some(Color::White));
+        state.fill = Paint::linear((0.0, 0.0), (1.0, 0.0), &[(0.0, Color::Red)]);
+        assert_eq!(state.fill_color(), None);
+        state.set_fill_color(Color::Blue);
+        assert_eq!(state.fill, Paint::Solid(Color::Blue));
+    }
+
+    #[test]
+    fn singular_transform_has_no_inverse() {
+        assert_eq!(Transform::scaling(0.0, 1.0).inverse(), None);
+        assert_eq!(Transform::scaling(f32::NAN, 1.0).inverse(), None);
+    }
+}
@@ -0,0 +1,288 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! Turns lines into the shapes that are filled to draw them, following the
+//! dashes, caps, joins and arrowheads in the layer's drawing state.
+
+use std::f32::consts::PI;
+use crate::{paint::Paint, state::*, raster, Color, FillRule, FPoint, Layer, Result};
+
+impl<'a> Layer<'a> {
+    /// Draws lines through lists of points, which have already been transformed and
+    /// validated. Each list comes with whether it is closed.
+    pub(crate) fn stroke_px(&mut self, parts: Vec<(Vec<FPoint>, bool)>, color: Color) -> Result<()> {
+        let state = &self.state;
+        let half_width = state.line_width / 2.0;
+        let mut shapes = Vec::new();
+        let mut thin_lines = Vec::new();
+        for (mut points, closed) in parts {
+            // Points that are the same as the one before them don't have a direction
+            points.dedup();
+            if !closed && points.len() > 1 {
+                // Both arrowheads come from the whole line, so that shortening it for
+                // one of them doesn't move the other
+                let start = state.start_arrow.map(|arrow| arrowhead(&points, arrow, true));
+                let end = state.end_arrow.map(|arrow| arrowhead(&points, arrow, false));
+                let last = points.len() - 1;
+                let length = (points[last].0 - points[0].0).hypot(points[last].1 - points[0].1);
+                if let Some((shape, back)) = start {
+                    points[0] = back;
+                    shapes.push(shape);
+                }
+                if let Some((shape, back)) = end {
+                    points[last] = back;
+                    shapes.push(shape);
+                }
+                // If the arrowheads on a single segment overlap, there's no line left between them
+                if let (1, Some(start), Some(end)) = (last, state.start_arrow, state.end_arrow) {
+                    if start.length + end.length >= length {
+                        continue;
+                    }
+                }
+                points.dedup();
+            }
+            for (piece, closed) in dash(&points, closed, &state.dash, state.dash_offset) {
+                if state.line_width <= 1.0 {
+                    thin_lines.push((piece, closed));
+                }
+                else {
+                    outline(&piece, closed, half_width, state, &mut shapes);
+                }
+            }
+        }
+
+        for (points, closed) in thin_lines {
+            for pair in points.windows(2) {
+                self.line_px(pair[0], pair[1], color)?;
+            }
+            if closed && points.len() > 2 {
+                self.line_px(points[points.len() - 1], points[0], color)?;
+            }
+        }
+        // Everything is filled at once, so that overlapping pieces aren't drawn twice
+        for shape in shapes.iter_mut() {
+            if signed_area(shape) < 0.0 {
+                shape.reverse();
+            }
+        }
+        self.fill_contours_px(&shapes, FillRule::NonZero, &Paint::Solid(color))
+    }
+}
+
+/// Gets the arrowhead at one end of a line, which needs at least two points that
+/// aren't the same, and where that end of the line should be moved to make room for it
+fn arrowhead(points: &[FPoint], arrow: Arrowhead, start: bool) -> (Vec<FPoint>, FPoint) {
+    let (tip, next) = if start { (0, 1) } else { (points.len() - 1, points.len() - 2) };
+    let (tip_point, next_point) = (points[tip], points[next]);
+    let (dx, dy) = (next_point.0 - tip_point.0, next_point.1 - tip_point.1);
+    let len = dx.hypot(dy);
+    let (ux, uy) = (dx / len, dy / len);
+    let back = (tip_point.0 + ux * arrow.length, tip_point.1 + uy * arrow.length);
+    let (nx, ny) = (-uy * arrow.width / 2.0, ux * arrow.width / 2.0);
+    let shape = vec![tip_point, (back.0 + nx, back.1 + ny), (back.0 - nx, back.1 - ny)];
+    // The line stops at the back of the arrowhead, or its last segment is removed if
+    // it's shorter than that
+    (shape, if arrow.length < len { back } else { next_point })
+}
+
+/// Splits a line into dashes. If there is no dash pattern, the line is returned
+/// as it is. Dashes are never closed.
+fn dash(points: &[FPoint], closed: bool, pattern: &[f32], offset: f32) -> Vec<(Vec<FPoint>, bool)> {
+    let mut pattern = pattern.to_vec();
+    if pattern.len() % 2 == 1 {
+        pattern.extend_from_within(..);
+    }
+    let total: f32 = pattern.iter().sum();
+    if points.is_empty() || total.is_nan() || total <= 0.0 || pattern.iter().any(|&n| n < 0.0) {
+        return vec![(points.to_vec(), closed)];
+    }
+
+    let mut i = 0;
+    let mut remaining = pattern[0];
+    let mut skip = offset.rem_euclid(total);
+    while skip >= remaining {
+        skip -= remaining;
+        i = (i + 1) % pattern.len();
+        remaining = pattern[i];
+    }
+    remaining -= skip;
+    let mut on = i % 2 == 0;
+
+    let mut edges: Vec<(FPoint, FPoint)> = points.windows(2).map(|pair| (pair[0], pair[1])).collect();
+    if closed {
+        edges.push((points[points.len() - 1], points[0]));
+    }
+    let mut dashes = Vec::new();
+    let mut current = vec![points[0]];
+    for (mut a, b) in edges {
+        let mut len = (b.0 - a.0).hypot(b.1 - a.1);
+        while len > remaining {
+            let t = remaining / len;
+            let p = (a.0 + (b.0 - a.0) * t, a.1 + (b.1 - a.1) * t);
+            if on {
+                current.push(p);
+                dashes.push((std::mem::take(&mut current), false));
+            }
+            else {
+                current = vec![p];
+            }
+            on = !on;
+            a = p;
+            len -= remaining;
+            i = (i + 1) % pattern.len();
+            remaining = pattern[i];
+        }
+        remaining -= len;
+        if on {
+            current.push(b);
+        }
+    }
+    if on && current.len() > 1 {
+        dashes.push((current, false));
+    }
+    dashes
+}
+
+/// Adds the shapes that make up a thick line to `shapes`
+fn outline(points: &[FPoint], closed: bool, half_width: f32, state: &DrawState, shapes: &mut Vec<Vec<FPoint>>) {
+    let mut points = points.to_vec();
+    points.dedup();
+    if points.len() == 1 {
+        // A dash of length 0 only shows up if it has caps
+        match state.line_cap {
+            LineCap::Butt => {},
+            LineCap::Round => shapes.push(circle(points[0], half_width)),
+            LineCap::Square => {
+                let (x, y) = points[0];
+                shapes.push(vec![
+                    (x - half_width, y - half_width), (x + half_width, y - half_width),
+                    (x + half_width, y + half_width), (x - half_width, y + half_width),
+                ]);
+            },
+        }
+        return;
+    }
+
+    let direction = |a: FPoint, b: FPoint| {
+        let len = (b.0 - a.0).hypot(b.1 - a.1);
+        ((b.0 - a.0) / len, (b.1 - a.1) / len)
+    };
+    let last = points.len() - 1;
+    let segment_count = if closed && points.len() > 2 { points.len() } else { last };
+    for i in 0..segment_count {
+        let (mut a, mut b) = (points[i], points[(i + 1) % points.len()]);
+        let d = direction(a, b);
+        if !closed && state.line_cap == LineCap::Square {
+            if i == 0 {
+                a = (a.0 - d.0 * half_width, a.1 - d.1 * half_width);
+            }
+            if i + 1 == last {
+                b = (b.0 + d.0 * half_width, b.1 + d.1 * half_width);
+            }
+        }
+        shapes.push(raster::thick_line_quad(a, b, half_width * 2.0).to_vec());
+    }
+
+    if !closed && state.line_cap == LineCap::Round {
+        shapes.push(circle(points[0], half_width));
+        shapes.push(circle(points[last], half_width));
+    }
+
+    let corners = if closed && points.len() > 2 { 0..points.len() } else { 1..last };
+    for i in corners {
+        let v = points[i];
+        let d0 = direction(points[(i + points.len() - 1) % points.len()], v);
+        let d1 = direction(v, points[(i + 1) % points.len()]);
+        let cross = d0.0 * d1.1 - d0.1 * d1.0;
+        if cross == 0.0 && d0.0 * d1.0 + d0.1 * d1.1 > 0.0 {
+            continue;
+        }
+        if state.line_join == LineJoin::Round {
+            shapes.push(circle(v, half_width));
+            continue;
+        }
+        // The outer side of the corner is the one where the edges of the lines move apart
+        let side = if cross > 0.0 { -half_width } else { half_width };
+        let n0 = (-d0.1 * side, d0.0 * side);
+        let n1 = (-d1.1 * side, d1.0 * side);
+        let outer0 = (v.0 + n0.0, v.1 + n0.1);
+        let outer1 = (v.0 + n1.0, v.1 + n1.1);
+        // The miter is longer the sharper the corner is. This is the ratio of its
+        // length to the line width.
+        let cos_half = ((1.0 + d0.0 * d1.0 + d0.1 * d1.1) / 2.0).sqrt();
+        if state.line_join == LineJoin::Miter && cos_half > 0.0 && 1.0 / cos_half <= state.miter_limit {
+            let bisector = (n0.0 + n1.0, n0.1 + n1.1);
+            let len = bisector.0.hypot(bisector.1);
+            let distance = half_width / cos_half;
+            let tip = (v.0 + bisector.0 / len * distance, v.1 + bisector.1 / len * distance);
+            shapes.push(vec![v, outer0, tip, outer1]);
+        }
+        else {
+            shapes.push(vec![v, outer0, outer1]);
+        }
+    }
+}
+
+/// Gets a polygon close enough to a circle that the difference can't be seen
+fn circle(center: FPoint, radius: f32) -> Vec<FPoint> {
+    let steps = ((radius * 2.0 * PI).ceil() as usize).max(8);
+    (0..steps)
+        .map(|i| {
+            let angle = i as f32 / steps as f32 * 2.0 * PI;
+            (center.0 + radius * angle.cos(), center.1 + radius * angle.sin())
+        })
+        .collect()
+}
+
+/// Gets the area of a polygon, which is negative if its points go counterclockwise
+/// (on screen, where y points down)
+fn signed_area(polygon: &[FPoint]) -> f32 {
+    let mut area = 0.0;
+    for (i, &a) in polygon.iter().enumerate() {
+        let b = polygon[(i + 1) % polygon.len()];
+        area += a.0 * b.1 - b.0 * a.1;
+    }
+    area / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ARROW: Arrowhead = Arrowhead { length: 2.0, width: 2.0 };
+
+    #[test]
+    fn arrowhead_shortens_line() {
+        let points = [(0.0, 0.0), (10.0, 0.0)];
+        let (shape, back) = arrowhead(&points, ARROW, false);
+        assert_eq!(shape, vec![(10.0, 0.0), (8.0, -1.0), (8.0, 1.0)]);
+        assert_eq!(back, (8.0, 0.0));
+        assert_eq!(arrowhead(&points, ARROW, true).1, (2.0, 0.0));
+    }
+
+    #[test]
+    fn arrowhead_removes_short_segment() {
+        let points = [(0.0, 0.0), (5.0, 0.0), (6.0, 0.0)];
+        assert_eq!(arrowhead(&points, ARROW, false).1, (5.0, 0.0));
+    }
+
+    #[test]
+    fn solid_line_isnt_dashed() {
+        let points = vec![(0.0, 0.0), (10.0, 0.0)];
+        assert_eq!(dash(&points, false, &[], 0.0), vec![(points.clone(), false)]);
+        assert_eq!(dash(&points, false, &[0.0, 0.0], 0.0), vec![(points.clone(), false)]);
+        assert_eq!(dash(&points, false, &[f32::NAN], 0.0), vec![(points, false)]);
+    }
+
+    #[test]
+    fn dashes_follow_pattern_and_offset() {
+        let points = [(0.0, 0.0), (10.0, 0.0)];
+        let dashes = dash(&points, false, &[3.0, 2.0], 0.0);
+        assert_eq!(dashes, vec![
+            (vec![(0.0, 0.0), (3.0, 0.0)], false),
+            (vec![(5.0, 0.0), (8.0, 0.0)], false),
+        ]);
+        let dashes = dash(&points, false, &[3.0, 2.0], 4.0);
+        assert_eq!(dashes[0], (vec![(1.0, 0.0), (4.0, 0.0)], false));
+    }
+}
@@ -0,0 +1,470 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module holds the types used to write text into the cells of a layer, and
+//! to lay it out in rectangles.
+
+use std::ops::Range;
+use crossterm::{style::{ContentStyle, StyledContent}, terminal};
+use crate::{border::Align, cell, Attribute, Attributes, Color, Layer, Rect, Result};
+
+/// The colors and attributes of text
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct TextStyle {
+    /// The color of the text. If it is `None`, the layer's stroke color is used
+    pub foreground: Option<Color>,
+
+    /// The color behind the text. If it is `None`, the color that was already behind
+    /// the cell is kept
+    pub background: Option<Color>,
+
+    /// Attributes such as bold, italic and underlined
+    pub attributes: Attributes,
+}
+
+impl TextStyle {
+    /// Creates a style that uses the layer's stroke color, with no attributes
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the color of the text
+    pub fn foreground(mut self, color: Color) -> Self {
+        self.foreground = Some(color);
+        self
+    }
+
+    /// Sets the color behind the text
+    pub fn background(mut self, color: Color) -> Self {
+        self.background = Some(color);
+        self
+    }
+
+    /// Adds an attribute. It can be called more than once to add more attributes
+    pub fn attribute(mut self, attribute: Attribute) -> Self {
+        self.attributes.set(attribute);
+        self
+    }
+
+    /// Gets the style of a cell drawn with this style
+    pub(crate) fn content_style(&self, stroke_color: Color) -> ContentStyle {
+        let mut style = ContentStyle::new();
+        style.foreground_color = Some(self.foreground.unwrap_or(stroke_color));
+        style.background_color = self.background;
+        style.attributes = self.attributes;
+        style
+    }
+}
+
+impl From<Color> for TextStyle {
+    fn from(color: Color) -> Self {
+        Self::new().foreground(color)
+    }
+}
+
+/// Some text with one style
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    /// The text
+    pub text: String,
+
+    /// The style of the text
+    pub style: TextStyle,
+}
+
+impl Span {
+    /// Creates a piece of text with a style
+    pub fn new(text: impl Into<String>, style: impl Into<TextStyle>) -> Self {
+        Self { text: text.into(), style: style.into() }
+    }
+}
+
+impl From<&str> for Span {
+    fn from(text: &str) -> Self {
+        Self::new(text, TextStyle::new())
+    }
+}
+
+impl From<String> for Span {
+    fn from(text: String) -> Self {
+        Self::new(text, TextStyle::new())
+    }
+}
+
+/// How lines of text are lined up with the sides of the rectangle they're in
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum TextAlign {
+    /// Lined up with the left side
+    Left,
+
+    /// In the middle
+    Center,
+
+    /// Lined up with the right side
+    Right,
+
+    /// Lined up with both sides, by stretching the spaces between words. The last
+    /// line of each paragraph is lined up with the left side.
+    Justify,
+}
+
+/// Where lines of text are broken when they are too long
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Wrap {
+    /// Between words. Words that are too long to fit on a line by themselves are
+    /// broken wherever they need to be.
+    Word,
+
+    /// Between any two characters
+    Char,
+}
+
+/// How text is laid out in a rectangle by `Layer::print_wrapped`
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TextLayout {
+    /// How lines are lined up with the sides. Defaults to `TextAlign::Left`
+    pub align: TextAlign,
+
+    /// Where the lines go between the top and bottom. Defaults to `Align::Start`
+    pub vertical_align: Align,
+
+    /// Where lines are broken. Defaults to `Wrap::Word`
+    pub wrap: Wrap,
+
+    /// If this is true and the text doesn't fit, the last line ends with `'…'`.
+    /// Defaults to `false`
+    pub ellipsis: bool,
+}
+
+impl Default for TextLayout {
+    fn default() -> Self {
+        Self { align: TextAlign::Left, vertical_align: Align::Start, wrap: Wrap::Word, ellipsis: false }
+    }
+}
+
+/// One line of text that has been laid out
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LaidOutLine {
+    /// The row of the line, from the top of the rectangle
+    pub y: u16,
+
+    /// Each character of the line, with its column (from the left of the rectangle)
+    /// and style
+    pub chars: Vec<(u16, char, TextStyle)>,
+}
+
+/// Text that has been laid out in a rectangle
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LaidOutText {
+    /// The lines that fit in the rectangle
+    pub lines: Vec<LaidOutLine>,
+
+    /// The text that didn't fit, which can be laid out somewhere else, such as on
+    /// the next page. This includes wide characters in a rectangle only one column
+    /// wide. It is empty if everything fit.
+    pub overflow: Vec<Span>,
+}
+
+/// A character and its style
+type StyledChar = (char, TextStyle);
+
+impl TextLayout {
+    /// Creates the default layout
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Lays out text in a rectangle of cells, without drawing it. Each `'\n'` starts
+    /// a new paragraph.
+    pub fn layout(&self, width: u16, height: u16, spans: &[Span]) -> LaidOutText {
+        let chars: Vec<StyledChar> = spans.iter()
+            .flat_map(|span| span.text.chars().map(move |c| (c, span.style)))
+            .filter(|&(c, _)| c == '\n' || cell::width(c) > 0)
+            .collect();
+
+        // Each line is the range of `chars` it has, and whether it ends a paragraph
+        let mut lines = Vec::new();
+        let mut start = 0;
+        loop {
+            let end = chars[start..].iter().position(|c| c.0 == '\n').map_or(chars.len(), |i| start + i);
+            for line in wrap(&chars, start..end, width as usize, self.wrap) {
+                lines.push((line, false));
+            }
+            if let Some(last) = lines.last_mut() {
+                last.1 = true;
+            }
+            if end == chars.len() {
+                break;
+            }
+            start = end + 1;
+        }
+
+        // A line is only wider than the rectangle if it has a character that is too
+        // wide to fit by itself. That line and everything after it overflow, so that
+        // no text is lost.
+        let visible = lines.iter().take(height as usize)
+            .position(|(line, _)| line_width(&chars[line.clone()]) > width as usize)
+            .unwrap_or_else(|| lines.len().min(height as usize));
+        let overflow = match lines.get(visible) {
+            Some((line, _)) => to_spans(&chars[line.start..]),
+            None => Vec::new(),
+        };
+        let top = match self.vertical_align {
+            Align::Start => 0,
+            Align::Center => (height as usize - visible) / 2,
+            Align::End => height as usize - visible,
+        };
+
+        let mut out = Vec::with_capacity(visible);
+        for (row, (range, ends_paragraph)) in lines[..visible].iter().enumerate() {
+            let mut line = chars[range.clone()].to_vec();
+            let mut justify = self.align == TextAlign::Justify && !ends_paragraph;
+            if self.ellipsis && !overflow.is_empty() && row + 1 == visible {
+                let style = line.last().map_or(TextStyle::default(), |c| c.1);
+                while !line.is_empty() && (line_width(&line) + 1 > width as usize || line[line.len() - 1].0 == ' ') {
+                    line.pop();
+                }
+                line.push(('…', style));
+                justify = false;
+            }
+
+            let extra = (width as usize).saturating_sub(line_width(&line));
+            let mut x = match self.align {
+                TextAlign::Center => extra / 2,
+                TextAlign::Right => extra,
+                _ => 0,
+            };
+            // When justifying, the extra space is shared between the spaces, with the
+            // ones on the left getting more if it can't be shared evenly
+            let spaces = line.iter().filter(|c| c.0 == ' ').count();
+            let mut space_index = 0;
+            let mut placed = Vec::with_capacity(line.len());
+            for (c, style) in line {
+                let char_width = cell::width(c);
+                if x + char_width > width as usize {
+                    break;
+                }
+                placed.push((x as u16, c, style));
+                x += char_width;
+                if justify && c == ' ' {
+                    x += extra / spaces + if space_index < extra % spaces { 1 } else { 0 };
+                    space_index += 1;
+                }
+            }
+            out.push(LaidOutLine { y: (top + row) as u16, chars: placed });
+        }
+        LaidOutText { lines: out, overflow }
+    }
+}
+
+/// Gets the number of cells some characters take up
+fn line_width(chars: &[StyledChar]) -> usize {
+    chars.iter().map(|c| cell::width(c.0)).sum()
+}
+
+/// Breaks a paragraph into lines no wider than `width`, and returns the range of
+/// `chars` each one has. The spaces where lines are broken are left out.
+fn wrap(chars: &[StyledChar], paragraph: Range<usize>, width: usize, mode: Wrap) -> Vec<Range<usize>> {
+    let char_width = |i: usize| cell::width(chars[i].0);
+    let mut lines = Vec::new();
+    let (mut start, mut end, mut i) = (paragraph.start, paragraph.start, paragraph.start);
+    // The width of the line up to `i`, including spaces that might be left out
+    let mut current_width = 0;
+    while i < paragraph.end {
+        let is_space = chars[i].0 == ' ';
+        // The next piece that can't be broken up, which is a word or a run of spaces
+        // (or just one character when breaking anywhere)
+        let piece_end = match mode {
+            Wrap::Char => i + 1,
+            Wrap::Word => (i..paragraph.end).find(|&j| (chars[j].0 == ' ') != is_space).unwrap_or(paragraph.end),
+        };
+        let piece_width: usize = (i..piece_end).map(char_width).sum();
+        if is_space {
+            // Spaces can go past the end of the line, since they're left out if the
+            // line is broken after them
+            current_width += piece_width;
+            i = piece_end;
+        }
+        else if current_width + piece_width <= width {
+            current_width += piece_width;
+            i = piece_end;
+            end = i;
+        }
+        else if end > start {
+            lines.push(start..end);
+            start = i;
+            end = i;
+            current_width = 0;
+        }
+        else {
+            // The piece is too wide to fit on a line by itself, so it is broken up
+            let mut j = i;
+            while j < piece_end && current_width + char_width(j) <= width {
+                current_width += char_width(j);
+                j += 1;
+            }
+            if j == start {
+                // Not even one character fits, but each line needs at least one
+                j += 1;
+            }
+            lines.push(start..j);
+            start = j;
+            end = j;
+            i = j;
+            current_width = 0;
+        }
+    }
+    // A paragraph that ends with a word that was broken up has nothing left for
+    // another line, but an empty paragraph is still an empty line
+    if end > start || lines.is_empty() {
+        lines.push(start..end);
+    }
+    lines
+}
+
+/// Turns characters back into spans, putting characters with the same style together
+fn to_spans(chars: &[StyledChar]) -> Vec<Span> {
+    let mut spans: Vec<Span> = Vec::new();
+    for &(c, style) in chars {
+        match spans.last_mut() {
+            Some(span) if span.style == style => span.text.push(c),
+            _ => spans.push(Span { text: c.to_string(), style }),
+        }
+    }
+    spans
+}
+
+impl<'a> Layer<'a> {
+    /// Writes text into the cells of the layer, starting at a cell (not a pixel).
+    /// Each `'\n'` starts a new row under the first character, and text that goes
+    /// past the edge of the layer is cut off. Wide characters, such as CJK characters
+    /// and most emoji, take up two cells.
+    ///
+    /// The text replaces whatever was in the cells, although if the style has no
+    /// background color, the color that was behind the cells stays.
+    pub fn print(&mut self, cell_x: u16, cell_y: u16, text: &str, style: impl Into<TextStyle>) -> Result<()> {
+        self.validate_cell_rect(Rect::new(cell_x, cell_y, 1, 1))?;
+        let style = style.into().content_style(self.state.stroke_color);
+        let (cols, rows) = terminal::size()?;
+        for (row, line) in (cell_y..rows).zip(text.split('\n')) {
+            let mut col = cell_x;
+            // Characters with a width of 0, such as combining marks, can't have a cell
+            // of their own, so they are left out
+            for c in line.chars().filter(|&c| cell::width(c) > 0) {
+                let width = cell::width(c) as u16;
+                if col as u32 + width as u32 > cols as u32 {
+                    break;
+                }
+                self.set_cell((col, row), StyledContent::new(style, c))?;
+                col += width;
+            }
+        }
+        Ok(())
+    }
+
+    /// Lays out text in a rectangle of cells and draws it, returning the lines that
+    /// were drawn and the text that didn't fit. Each `'\n'` starts a new paragraph.
+    pub fn print_wrapped(&mut self, rect: Rect, spans: &[Span], layout: &TextLayout) -> Result<LaidOutText> {
+        self.validate_cell_rect(rect)?;
+        let text = layout.layout(rect.width, rect.height, spans);
+        let stroke_color = self.state.stroke_color;
+        for line in text.lines.iter() {
+            for &(x, c, style) in line.chars.iter() {
+                let cell = StyledContent::new(style.content_style(stroke_color), c);
+                self.set_cell((rect.x + x, rect.y + line.y), cell)?;
+            }
+        }
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Vec<StyledChar> {
+        text.chars().map(|c| (c, TextStyle::new())).collect()
+    }
+
+    fn wrapped(text: &str, width: usize, mode: Wrap) -> Vec<String> {
+        let chars = chars(text);
+        wrap(&chars, 0..chars.len(), width, mode).into_iter()
+            .map(|line| chars[line].iter().map(|c| c.0).collect())
+            .collect()
+    }
+
+    fn lines(text: &LaidOutText) -> Vec<(u16, u16, String)> {
+        text.lines.iter()
+            .map(|line| (line.y, line.chars.first().map_or(0, |c| c.0), line.chars.iter().map(|c| c.1).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn words_are_kept_together() {
+        assert_eq!(wrapped("hello world foo", 11, Wrap::Word), ["hello world", "foo"]);
+        assert_eq!(wrapped("hello   world", 8, Wrap::Word), ["hello", "world"]);
+        assert_eq!(wrapped("", 5, Wrap::Word), [""]);
+    }
+
+    #[test]
+    fn long_words_are_broken() {
+        assert_eq!(wrapped("abcdefgh", 3, Wrap::Word), ["abc", "def", "gh"]);
+        assert_eq!(wrapped("a bcdefg", 3, Wrap::Word), ["a", "bcd", "efg"]);
+        assert_eq!(wrapped("abc", 0, Wrap::Word), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn char_wrap_breaks_anywhere() {
+        assert_eq!(wrapped("ab cd", 3, Wrap::Char), ["ab", "cd"]);
+        assert_eq!(wrapped("abcde", 2, Wrap::Char), ["ab", "cd", "e"]);
+    }
+
+    #[test]
+    fn wide_characters_take_two_columns() {
+        assert_eq!(wrapped("中文字", 5, Wrap::Word), ["中文", "字"]);
+        assert_eq!(wrapped("中文字", 1, Wrap::Char), ["中", "文", "字"]);
+    }
+
+    #[test]
+    fn lines_are_aligned() {
+        let spans = [Span::from("ab\nabcd")];
+        let mut layout = TextLayout { align: TextAlign::Right, ..TextLayout::new() };
+        assert_eq!(lines(&layout.layout(6, 2, &spans)), [(0, 4, "ab".to_string()), (1, 2, "abcd".to_string())]);
+        layout.align = TextAlign::Center;
+        layout.vertical_align = Align::End;
+        assert_eq!(lines(&layout.layout(6, 4, &spans)), [(2, 2, "ab".to_string()), (3, 1, "abcd".to_string())]);
+    }
+
+    #[test]
+    fn justified_lines_fill_width() {
+        let layout = TextLayout { align: TextAlign::Justify, ..TextLayout::new() };
+        let text = layout.layout(7, 2, &[Span::from("aa bb cc dd")]);
+        let columns: Vec<u16> = text.lines[0].chars.iter().map(|c| c.0).collect();
+        assert_eq!(columns, [0, 1, 2, 5, 6]);
+        // The last line of a paragraph isn't justified
+        assert_eq!(lines(&text)[1], (1, 0, "cc dd".to_string()));
+    }
+
+    #[test]
+    fn overflow_and_ellipsis() {
+        let spans = [Span::from("one two three")];
+        let mut layout = TextLayout::new();
+        let text = layout.layout(5, 2, &spans);
+        assert_eq!(lines(&text), [(0, 0, "one".to_string()), (1, 0, "two".to_string())]);
+        assert_eq!(text.overflow, [Span::from("three")]);
+        layout.ellipsis = true;
+        assert_eq!(lines(&layout.layout(5, 1, &spans)), [(0, 0, "one…".to_string())]);
+        assert!(layout.layout(13, 1, &spans).overflow.is_empty());
+    }
+
+    #[test]
+    fn too_wide_characters_overflow() {
+        let layout = TextLayout { wrap: Wrap::Char, ..TextLayout::new() };
+        let text = layout.layout(1, 3, &[Span::from("a中b")]);
+        assert_eq!(lines(&text), [(0, 0, "a".to_string())]);
+        assert_eq!(text.overflow, [Span::from("中b")]);
+        let text = layout.layout(1, 3, &[Span::from("中")]);
+        assert!(text.lines.is_empty());
+        assert_eq!(text.overflow, [Span::from("中")]);
+    }
+}
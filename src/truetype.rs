@@ -0,0 +1,177 @@
+// Copyright 2020 Liam Bloom
+// SPDX-License-Identifier: Apache-2.0
+
+//! This module holds TrueType and OpenType fonts, which are made of curves
+//! instead of pixels, so they can be drawn at any size. It is only included with
+//! the `truetype` feature.
+
+use std::io::Read;
+use ttf_parser::{Face, OutlineBuilder};
+use crate::{error::ErrorKind, path::Path, paint::IntoPaint, FillRule, FPoint, Layer, Result};
+
+/// A TrueType or OpenType font, used by `Layer::draw_text_ttf`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutlineFont {
+    data: Vec<u8>,
+    /// These are in font units, and are read when the font is loaded, so that
+    /// measuring text doesn't need to parse the font
+    ascender: i16,
+    descender: i16,
+    line_gap: i16,
+    units_per_em: u16,
+}
+
+impl OutlineFont {
+    /// Reads a font from the contents of a `.ttf` or `.otf` file. For font
+    /// collections, the first font is used.
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        let face = Face::from_slice(&data, 0).map_err(|err| ErrorKind::FontError(err.to_string()))?;
+        let (ascender, descender, line_gap) = (face.ascender(), face.descender(), face.line_gap());
+        let units_per_em = face.units_per_em().filter(|&units| units > 0).unwrap_or(1000);
+        Ok(Self { data, ascender, descender, line_gap, units_per_em })
+    }
+
+    /// Reads a font from a `.ttf` or `.otf` file
+    pub fn from_reader(src: &mut impl Read) -> Result<Self> {
+        let mut data = Vec::new();
+        src.read_to_end(&mut data)?;
+        Self::from_bytes(data)
+    }
+
+    /// The distance, in pixels, from the top of a line to its baseline, when the
+    /// font is drawn `size` pixels tall
+    pub fn ascent(&self, size: f32) -> f32 {
+        self.ascender as f32 * self.scale(size)
+    }
+
+    /// The distance, in pixels, from the top of one line to the top of the next,
+    /// when the font is drawn `size` pixels tall
+    pub fn line_height(&self, size: f32) -> f32 {
+        (self.ascender as f32 - self.descender as f32 + self.line_gap as f32) * self.scale(size)
+    }
+
+    /// Gets the width, in pixels, of the widest line of some text
+    pub fn text_width(&self, text: &str, size: f32) -> f32 {
+        let face = self.face();
+        let scale = self.scale(size);
+        text.split('\n')
+            .map(|line| {
+                line.chars()
+                    .filter_map(|c| face.glyph_index(c))
+                    .filter_map(|glyph| face.glyph_hor_advance(glyph))
+                    .map(|advance| advance as f32 * scale)
+                    .sum::<f32>()
+            })
+            .fold(0.0, f32::max)
+    }
+
+    /// Gets the width and height, in pixels, of some text
+    pub fn measure(&self, text: &str, size: f32) -> (f32, f32) {
+        (self.text_width(text, size), text.split('\n').count() as f32 * self.line_height(size))
+    }
+
+    /// Gets the outlines of some text, with the top left corner of the first line at `p`
+    fn outline(&self, text: &str, size: f32, p: FPoint) -> Path {
+        let face = self.face();
+        let (scale, line_height) = (self.scale(size), self.line_height(size));
+        let mut builder = PathBuilder { path: Path::new(), pen: (p.0, p.1 + self.ascent(size)), scale };
+        for line in text.split('\n') {
+            builder.pen.0 = p.0;
+            for glyph in line.chars().filter_map(|c| face.glyph_index(c)) {
+                face.outline_glyph(glyph, &mut builder);
+                builder.pen.0 += face.glyph_hor_advance(glyph).unwrap_or(0) as f32 * scale;
+            }
+            builder.pen.1 += line_height;
+        }
+        builder.path
+    }
+
+    /// Parses the font, which is only needed for its glyphs
+    fn face(&self) -> Face<'_> {
+        Face::from_slice(&self.data, 0).expect("The font was checked when it was loaded")
+    }
+
+    /// How many pixels one font unit is
+    fn scale(&self, size: f32) -> f32 {
+        size / self.units_per_em as f32
+    }
+}
+
+/// Turns the outline of a glyph into a path. Fonts measure y going up from the
+/// baseline, so it is flipped.
+struct PathBuilder {
+    path: Path,
+    pen: FPoint,
+    scale: f32,
+}
+
+impl PathBuilder {
+    fn point(&self, x: f32, y: f32) -> FPoint {
+        (self.pen.0 + x * self.scale, self.pen.1 - y * self.scale)
+    }
+}
+
+impl OutlineBuilder for PathBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.path.move_to(p);
+    }
+
+    fn line_to(&mut self, x: f32, y: f32) {
+        let p = self.point(x, y);
+        self.path.line_to(p);
+    }
+
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (control, p) = (self.point(x1, y1), self.point(x, y));
+        self.path.quad_to(control, p);
+    }
+
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (control1, control2, p) = (self.point(x1, y1), self.point(x2, y2), self.point(x, y));
+        self.path.cubic_to(control1, control2, p);
+    }
+
+    fn close(&mut self) {
+        self.path.close();
+    }
+}
+
+impl<'a> Layer<'a> {
+    /// Draws anti-aliased text with a TrueType or OpenType font, `size` pixels tall,
+    /// with the top left corner of the first line at `p`. Each `'\n'` starts a new
+    /// line, and the parts of the text past the edge of the layer are cut off. If
+    /// `paint` is `None`, the current fill paint is used.
+    pub fn draw_text_ttf(&mut self, p: FPoint, text: &str, font: &OutlineFont, size: f32, paint: impl IntoPaint) -> Result<()> {
+        let paint = self.fill_paint(paint);
+        self.validate_f_points(&[self.state.transform.apply(p)])?;
+        let contours: Vec<Vec<FPoint>> = font.outline(text, size, p)
+            .flatten(&self.state.transform)
+            .into_iter()
+            .map(|(points, _)| points)
+            .collect();
+        self.fill_contours_aa_px(&contours, FillRule::NonZero, &paint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn font() -> OutlineFont {
+        OutlineFont { data: Vec::new(), ascender: 800, descender: -200, line_gap: 100, units_per_em: 1000 }
+    }
+
+    #[test]
+    fn metrics_are_scaled_to_size() {
+        let font = font();
+        assert_eq!(font.ascent(20.0), 16.0);
+        assert_eq!(font.line_height(20.0), 22.0);
+    }
+
+    #[test]
+    fn invalid_font_is_an_error() {
+        assert!(matches!(OutlineFont::from_bytes(vec![0; 16]), Err(ErrorKind::FontError(_))));
+        assert!(matches!(OutlineFont::from_bytes(Vec::new()), Err(ErrorKind::FontError(_))));
+    }
+}